@@ -0,0 +1,105 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{Expr, ItemFn, LitBool, meta::ParseNestedMeta, parse_macro_input, parse_quote};
+
+struct Args {
+    queue_status: Expr,
+    wait_flags: Expr,
+    translate: bool,
+    dispatch: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            queue_status: parse_quote!(::async_messages::__private::QS_ALLINPUT),
+            wait_flags: parse_quote!(::async_messages::__private::MWMO_NONE),
+            translate: true,
+            dispatch: true,
+        }
+    }
+}
+
+impl Args {
+    fn parse(&mut self, meta: ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("queue_status") {
+            self.queue_status = meta.value()?.parse()?;
+        } else if meta.path.is_ident("wait_flags") {
+            self.wait_flags = meta.value()?.parse()?;
+        } else if meta.path.is_ident("translate") {
+            self.translate = meta.value()?.parse::<LitBool>()?.value;
+        } else if meta.path.is_ident("dispatch") {
+            self.dispatch = meta.value()?.parse::<LitBool>()?.value;
+        } else {
+            return Err(
+                meta.error("expected `queue_status`, `wait_flags`, `translate` or `dispatch`")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Turns an async fn taking a single `MSG` into a message loop that calls it for every message until `WM_QUIT`.
+///
+/// The annotated function becomes `async fn() -> windows::core::Result<QuitCode>`. Every message is translated and
+/// dispatched before the body runs, which can be turned off with `translate = false` and `dispatch = false`. The
+/// flags default to `QS_ALLINPUT` and `MWMO_NONE`.
+///
+/// ```ignore
+/// #[message_loop(queue_status = QS_ALLPOSTMESSAGE, dispatch = false)]
+/// async fn handle(msg: MSG) {
+///     println!("{}", msg.message);
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn message_loop(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut parsed_args = Args::default();
+    let parser = syn::meta::parser(|meta| parsed_args.parse(meta));
+    parse_macro_input!(args with parser);
+
+    let item = parse_macro_input!(item as ItemFn);
+
+    if item.sig.asyncness.is_none() {
+        return syn::Error::new_spanned(item.sig.fn_token, "the function must be async")
+            .to_compile_error()
+            .into();
+    }
+
+    if item.sig.inputs.len() != 1 {
+        return syn::Error::new_spanned(&item.sig.inputs, "the function must take a single `MSG`")
+            .to_compile_error()
+            .into();
+    }
+
+    let Args {
+        queue_status,
+        wait_flags,
+        translate,
+        dispatch,
+    } = parsed_args;
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = item;
+    let ident = &sig.ident;
+    let inputs = &sig.inputs;
+    let output = &sig.output;
+
+    quote! {
+        #(#attrs)*
+        #vis async fn #ident() -> ::async_messages::__private::Result<::async_messages::QuitCode> {
+            async fn handler(#inputs) #output #block
+
+            ::async_messages::for_each_message(#queue_status, #wait_flags, |msg| async move {
+                ::async_messages::__private::translate_and_dispatch(&msg, #translate, #dispatch);
+                handler(msg).await;
+            })
+            .await
+        }
+    }
+    .into()
+}
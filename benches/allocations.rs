@@ -0,0 +1,80 @@
+//! Counts the allocations per batch of `MessageWaiter::wait_snapshot` and of collecting every batch into a new `Vec`.
+//!
+//! Every iteration posts a batch of messages to the current thread and drains it, so the wait takes the fast path and
+//! the counts only differ in how the batch is stored.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use async_messages::{MessageWaiter, block_on_reactor};
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{MSG, MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER},
+};
+
+const ITERATIONS: usize = 10_000;
+const BATCH_SIZE: usize = 32;
+
+/// Counts allocations and reallocations, but not deallocations.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn post_batch() {
+    for _ in 0..BATCH_SIZE {
+        unsafe { PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)) }.unwrap();
+    }
+}
+
+/// Returns the average number of allocations per batch, after a warm-up batch that lets the buffer grow.
+fn allocations_per_batch(mut drain: impl AsyncFnMut(&mut MessageWaiter) -> usize) -> f64 {
+    block_on_reactor(async {
+        let mut waiter = MessageWaiter::new(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+        post_batch();
+        assert_eq!(drain(&mut waiter).await, BATCH_SIZE);
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+
+        for _ in 0..ITERATIONS {
+            post_batch();
+            assert_eq!(drain(&mut waiter).await, BATCH_SIZE);
+        }
+
+        (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / ITERATIONS as f64
+    })
+    .unwrap()
+}
+
+fn main() {
+    let snapshot =
+        allocations_per_batch(async |waiter| waiter.wait_snapshot().await.unwrap().len());
+    let collected = allocations_per_batch(async |waiter| {
+        waiter.wait().await.unwrap().collect::<Vec<MSG>>().len()
+    });
+
+    println!("wait_snapshot: {snapshot:.2} allocations per batch of {BATCH_SIZE}");
+    println!("collect into Vec: {collected:.2} allocations per batch of {BATCH_SIZE}");
+}
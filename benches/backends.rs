@@ -0,0 +1,59 @@
+//! Compares the cost of a wait round trip with both backends.
+//!
+//! Every iteration posts a message to the current thread and waits for it, so the wait is armed and completed once
+//! per iteration.
+
+use std::{
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use async_messages::{Backend, MessageWaiter, block_on_reactor};
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER},
+};
+
+const ITERATIONS: u32 = 10_000;
+
+fn round_trips(backend: Backend) -> Duration {
+    let thread_id = unsafe { GetCurrentThreadId() };
+
+    // Posting from another thread makes it likely that the wait is actually armed instead of taking the fast path.
+    let (go, requests) = mpsc::channel::<()>();
+    let poster = std::thread::spawn(move || {
+        for () in requests {
+            unsafe { PostThreadMessageW(thread_id, WM_USER, WPARAM(0), LPARAM(0)) }.unwrap();
+        }
+    });
+
+    let elapsed = block_on_reactor(async {
+        let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .backend(backend)
+            .build()
+            .unwrap();
+
+        let start = Instant::now();
+
+        for _ in 0..ITERATIONS {
+            go.send(()).unwrap();
+            while waiter.wait().await.unwrap().count() == 0 {}
+        }
+
+        start.elapsed()
+    })
+    .unwrap();
+
+    drop(go);
+    poster.join().unwrap();
+
+    elapsed
+}
+
+fn main() {
+    for backend in [Backend::Threadpool, Backend::Reactor] {
+        let elapsed = round_trips(backend);
+        println!("{backend:?}: {:?} per round trip", elapsed / ITERATIONS);
+    }
+}
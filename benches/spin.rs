@@ -0,0 +1,68 @@
+//! Compares the latency of a wake-up with and without a spin budget.
+//!
+//! Every iteration asks another thread to post a message shortly after the wait started, and measures how long it
+//! takes from posting the message until the wait resolves.
+
+use std::{
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use async_messages::{MessageWaiter, block_on_reactor};
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER},
+};
+
+const ITERATIONS: u32 = 10_000;
+const POST_DELAY: Duration = Duration::from_micros(20);
+
+fn latency(spin_budget: Option<Duration>) -> Duration {
+    let thread_id = unsafe { GetCurrentThreadId() };
+
+    let (go, requests) = mpsc::channel::<()>();
+    let (posted, posted_at) = mpsc::channel();
+    let poster = std::thread::spawn(move || {
+        for () in requests {
+            let deadline = Instant::now() + POST_DELAY;
+            while Instant::now() < deadline {}
+
+            posted.send(Instant::now()).unwrap();
+            unsafe { PostThreadMessageW(thread_id, WM_USER, WPARAM(0), LPARAM(0)) }.unwrap();
+        }
+    });
+
+    let total = block_on_reactor(async {
+        let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .spin_budget(spin_budget)
+            .build()
+            .unwrap();
+
+        let mut total = Duration::ZERO;
+
+        for _ in 0..ITERATIONS {
+            go.send(()).unwrap();
+            while waiter.wait().await.unwrap().count() == 0 {}
+            total += posted_at.recv().unwrap().elapsed();
+        }
+
+        total
+    })
+    .unwrap();
+
+    drop(go);
+    poster.join().unwrap();
+
+    total
+}
+
+fn main() {
+    for spin_budget in [None, Some(Duration::from_micros(100))] {
+        let total = latency(spin_budget);
+        println!(
+            "spin budget {spin_budget:?}: {:?} from posting to wake-up",
+            total / ITERATIONS
+        );
+    }
+}
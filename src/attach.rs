@@ -0,0 +1,47 @@
+use windows::Win32::UI::WindowsAndMessaging::{
+    MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, QUEUE_STATUS_FLAGS,
+};
+
+use crate::{
+    MWMO_QUEUEATTACH,
+    msg_future::{InputEventFuture, MessageIterator, WaitOptions, narrow_flags, queue_status},
+};
+
+/// What woke up [`wait_for_attached_input`].
+#[must_use]
+pub enum AttachedInput {
+    /// Messages are available.
+    Messages(MessageIterator<'static>),
+    /// Another thread's input queue was attached to or detached from this thread's, and no matching messages are
+    /// queued.
+    QueueAttached,
+}
+
+/// Waits for messages, also waking up when another thread's input queue is attached to or detached from this thread's.
+///
+/// `AttachThreadInput` merges the input queues of two threads, so that input for either of them ends up in the shared
+/// queue. This adds `MWMO_QUEUEATTACH` to `wait_flags`, which makes the attach or detach itself wake the wait, so the
+/// caller can react to the changed queue instead of sleeping through input that was already in the other thread's
+/// queue. The wait can't tell an attach from a detach; it resolves to [`AttachedInput::QueueAttached`] whenever it was
+/// woken up without any matching messages being queued. Waiting again then wakes up for input to either thread.
+///
+/// The request to be woken up by attaches is withdrawn once the wait is done, so later waits on this thread that don't
+/// pass `MWMO_QUEUEATTACH` aren't woken up by them.
+pub async fn wait_for_attached_input(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+) -> windows::core::Result<AttachedInput> {
+    let (queue_status_flags, wait_flags) = narrow_flags(
+        queue_status_flags.into(),
+        wait_flags.into() | MWMO_QUEUEATTACH,
+    )?;
+
+    let messages =
+        InputEventFuture::new(queue_status_flags, wait_flags, WaitOptions::default()).await?;
+
+    if queue_status(queue_status_flags, wait_flags)? > 0 {
+        Ok(AttachedInput::Messages(messages))
+    } else {
+        Ok(AttachedInput::QueueAttached)
+    }
+}
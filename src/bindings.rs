@@ -14,3 +14,9 @@ pub unsafe fn NtUserGetQueueStatusReadonly(
             .or_else(|_| nt_user_call::functions::NtUserGetQueueStatus(wake_mask_and_flags))
     }
 }
+
+/// Whether `NtUserGetQueueStatusReadonly` is available, rather than falling back to `NtUserGetQueueStatus`.
+pub fn has_readonly_queue_status() -> bool {
+    // A zero mask doesn't query anything, so this has no side effects either way.
+    unsafe { c::NtUserGetQueueStatusReadonly(0) }.is_ok()
+}
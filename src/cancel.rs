@@ -0,0 +1,44 @@
+use std::{
+    future::{Future, poll_fn},
+    pin::pin,
+    task::Poll,
+};
+
+use tokio_util::sync::CancellationToken;
+use windows::Win32::UI::WindowsAndMessaging::{
+    MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, QUEUE_STATUS_FLAGS,
+};
+
+use crate::{MessageIterator, wait_for_messages};
+
+/// The outcome of [`wait_for_messages_until`].
+#[must_use]
+pub enum WaitOutcome {
+    /// Messages are available.
+    Messages(MessageIterator<'static>),
+    /// The token was cancelled before any messages arrived.
+    Cancelled,
+}
+
+/// Waits for messages like [`wait_for_messages`], giving up once `token` is cancelled.
+///
+/// Cancellation takes priority: if the token is already cancelled, no wait is armed at all. Otherwise, the pending
+/// wait is cancelled as soon as the token is, which doesn't block on the threadpool callback unless it's running at
+/// that very moment. The messages stay queued either way.
+pub async fn wait_for_messages_until(
+    token: &CancellationToken,
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+) -> windows::core::Result<WaitOutcome> {
+    let mut wait = pin!(wait_for_messages(queue_status_flags, wait_flags)?);
+    let mut cancelled = pin!(token.cancelled());
+
+    poll_fn(|cx| {
+        if cancelled.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Ok(WaitOutcome::Cancelled));
+        }
+
+        wait.as_mut().poll(cx).map_ok(WaitOutcome::Messages)
+    })
+    .await
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+
+use windows::Win32::UI::WindowsAndMessaging::{
+    MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, QUEUE_STATUS_FLAGS,
+};
+
+/// The state of a wait.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitState {
+    /// The wait hasn't been armed yet, or has been re-armed and is about to become pending.
+    NotPending,
+    /// The wait is armed and waiting for the input event.
+    Pending,
+    /// The input event has been signaled.
+    Ready,
+    /// The future has been dropped while the wait was pending.
+    Cancelled,
+}
+
+/// A snapshot of a wait's internals, meant to be pasted into bug reports.
+///
+/// Taking the snapshot is cheap and has no side effects.
+#[derive(Clone, Copy, Debug)]
+pub struct WaitStateSnapshot {
+    pub state: WaitState,
+    pub queue_status_flags: QUEUE_STATUS_FLAGS,
+    pub wait_flags: MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS,
+    /// Whether a threadpool wait is currently set on the input event.
+    pub wait_armed: bool,
+    /// Whether a waker has been registered by polling the future.
+    pub waker_registered: bool,
+    /// The last value returned by `NtUserGetQueueStatusReadonly`, if the queue status has been read.
+    pub last_queue_status: Option<u32>,
+}
+
+impl fmt::Display for WaitStateSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "state: {:?}, queue status flags: {:#06x}, wait flags: {:#06x}, wait armed: {}, waker registered: {}, \
+             last queue status: ",
+            self.state,
+            self.queue_status_flags.0,
+            self.wait_flags.0,
+            self.wait_armed,
+            self.waker_registered
+        )?;
+
+        match self.last_queue_status {
+            Some(queue_status) => write!(f, "{queue_status:#010x}"),
+            None => f.write_str("none"),
+        }
+    }
+}
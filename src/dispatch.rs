@@ -0,0 +1,216 @@
+use std::mem::MaybeUninit;
+
+use windows::Win32::{
+    Foundation::{LRESULT, WPARAM},
+    UI::WindowsAndMessaging::{
+        DispatchMessageW, MSG, MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, PM_NOREMOVE,
+        PM_QS_SENDMESSAGE, PeekMessageW, QUEUE_STATUS_FLAGS, TranslateMessage, WM_QUIT, WM_TIMER,
+    },
+};
+
+use crate::{
+    MessageWaiter,
+    msg_future::{MessageIterator, narrow_flags, queue_status},
+};
+
+/// The exit code passed to `PostQuitMessage`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct QuitCode(pub i32);
+
+impl From<WPARAM> for QuitCode {
+    fn from(value: WPARAM) -> Self {
+        // PostQuitMessage sign-extends the exit code into wParam, so only the low 32 bits carry it.
+        // Truncating before reinterpreting keeps negative exit codes intact on 64-bit targets.
+        Self(value.0 as u32 as i32)
+    }
+}
+
+impl From<QuitCode> for i32 {
+    fn from(value: QuitCode) -> Self {
+        value.0
+    }
+}
+
+/// Waits until `WM_QUIT` is received, passing every other message to `handler`.
+///
+/// Messages that are still queued behind `WM_QUIT` are left in the queue.
+pub async fn wait_for_quit(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    mut handler: impl FnMut(&MSG),
+) -> windows::core::Result<QuitCode> {
+    let mut waiter = MessageWaiter::new(queue_status_flags, wait_flags)?;
+
+    loop {
+        for msg in waiter.wait().await? {
+            if msg.message == WM_QUIT {
+                return Ok(msg.wParam.into());
+            }
+
+            handler(&msg);
+        }
+    }
+}
+
+/// Waits for messages and awaits `handler` for each of them in turn, until `WM_QUIT` is received.
+///
+/// Messages are handled strictly sequentially: the next message isn't retrieved until the handler for the previous
+/// one has completed, so a slow handler delays every message behind it. Messages that arrive while a handler is
+/// running are picked up before waiting again, so the loop only blocks once the queue is empty.
+///
+/// Messages that are still queued behind `WM_QUIT` are left in the queue.
+pub async fn for_each_message<F: Future<Output = ()>>(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    mut handler: impl FnMut(MSG) -> F,
+) -> windows::core::Result<QuitCode> {
+    let mut waiter = MessageWaiter::new(queue_status_flags, wait_flags)?;
+
+    loop {
+        for msg in waiter.wait().await? {
+            if msg.message == WM_QUIT {
+                return Ok(msg.wParam.into());
+            }
+
+            handler(msg).await;
+        }
+    }
+}
+
+/// Processes the messages other threads sent to this thread with `SendMessageW` and friends, without removing any
+/// posted messages from the queue.
+///
+/// Sent messages are never retrieved: `PeekMessageW` calls the window procedure for them directly before looking for a
+/// posted message, no matter whether `PM_REMOVE` or `PM_NOREMOVE` is passed. Draining a [`MessageIterator`] therefore
+/// processes sent messages as a side effect, too. This restricts `PeekMessageW` to sent messages with
+/// `PM_QS_SENDMESSAGE` and doesn't remove anything, so the posted messages stay queued in their original order.
+pub fn process_sent_messages() {
+    let mut msg = MaybeUninit::uninit();
+
+    // Sent messages are never returned, so there's nothing to learn from the result.
+    _ = unsafe {
+        PeekMessageW(
+            msg.as_mut_ptr(),
+            None,
+            0,
+            0,
+            PM_NOREMOVE | PM_QS_SENDMESSAGE,
+        )
+    };
+}
+
+/// Translates and dispatches `msg`, handing thread messages to `on_thread_message` instead.
+///
+/// `DispatchMessageW` silently drops messages without a window, which is how messages posted via
+/// `PostThreadMessageW` arrive. `WM_TIMER` messages carrying a `TIMERPROC` are the exception and are still
+/// dispatched, as that's what invokes the timer callback.
+pub fn dispatch_message(msg: &MSG, on_thread_message: impl FnOnce(&MSG)) {
+    dispatch_message_with_result(msg, on_thread_message);
+}
+
+/// Like [`dispatch_message`], but returns what `DispatchMessageW` returned, which is the window procedure's result.
+///
+/// Returns `None` for thread messages handed to `on_thread_message`.
+pub fn dispatch_message_with_result(
+    msg: &MSG,
+    on_thread_message: impl FnOnce(&MSG),
+) -> Option<LRESULT> {
+    if is_thread_message(msg) {
+        on_thread_message(msg);
+        return None;
+    }
+
+    unsafe {
+        _ = TranslateMessage(msg);
+        Some(DispatchMessageW(msg))
+    }
+}
+
+/// Whether `msg` has no window to dispatch it to, see [`dispatch_message`].
+fn is_thread_message(msg: &MSG) -> bool {
+    msg.hwnd.0.is_null() && !(msg.message == WM_TIMER && msg.lParam.0 != 0)
+}
+
+/// Used by the `message_loop` attribute. Thread messages are left to the loop's body, like [`dispatch_message`] does.
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub fn translate_and_dispatch(msg: &MSG, translate: bool, dispatch: bool) {
+    if is_thread_message(msg) {
+        return;
+    }
+
+    unsafe {
+        if translate {
+            _ = TranslateMessage(msg);
+        }
+
+        if dispatch {
+            DispatchMessageW(msg);
+        }
+    }
+}
+
+/// Translates and dispatches messages until `WM_QUIT` is received.
+///
+/// Thread messages can't be dispatched and are discarded; use [`run_message_loop_with`] to handle them.
+pub async fn run_message_loop(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+) -> windows::core::Result<QuitCode> {
+    run_message_loop_with(queue_status_flags, wait_flags, |_| {}).await
+}
+
+/// Translates and dispatches messages until `WM_QUIT` is received, passing thread messages to `on_thread_message`.
+pub async fn run_message_loop_with(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    mut on_thread_message: impl FnMut(&MSG),
+) -> windows::core::Result<QuitCode> {
+    wait_for_quit(queue_status_flags, wait_flags, |msg| {
+        dispatch_message(msg, &mut on_thread_message)
+    })
+    .await
+}
+
+/// Translates and dispatches messages until `WM_QUIT` is received, passing the result of every dispatched message to
+/// `on_dispatched` and thread messages to `on_thread_message`.
+pub async fn run_message_loop_with_results(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    mut on_thread_message: impl FnMut(&MSG),
+    mut on_dispatched: impl FnMut(&MSG, LRESULT),
+) -> windows::core::Result<QuitCode> {
+    wait_for_quit(queue_status_flags, wait_flags, |msg| {
+        if let Some(result) = dispatch_message_with_result(msg, &mut on_thread_message) {
+            on_dispatched(msg, result);
+        }
+    })
+    .await
+}
+
+/// Drains the queue, passing every message to `dispatch`, until the queue reports no more messages.
+///
+/// This never waits: it returns as soon as a full pass over the queue yields nothing and the queue status for the
+/// given flags is empty. As dispatching can generate new messages, e.g. `TranslateMessage` posting `WM_CHAR`, the
+/// queue is drained again until that's the case. `dispatch` has to validate the update region when handling
+/// `WM_PAINT`, otherwise the queue never becomes empty.
+pub fn drain_until_empty(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    mut dispatch: impl FnMut(&MSG),
+) -> windows::core::Result<()> {
+    let (queue_status_flags, wait_flags) =
+        narrow_flags(queue_status_flags.into(), wait_flags.into())?;
+
+    loop {
+        let mut drained = false;
+        for msg in MessageIterator::default() {
+            dispatch(&msg);
+            drained = true;
+        }
+
+        if !drained && queue_status(queue_status_flags, wait_flags)? == 0 {
+            return Ok(());
+        }
+    }
+}
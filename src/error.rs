@@ -0,0 +1,113 @@
+use windows::{
+    Win32::System::{
+        Com::IErrorInfo,
+        Ole::{CreateErrorInfo, SetErrorInfo},
+    },
+    core::{HSTRING, IUnknown, Interface, PCWSTR},
+};
+
+/// A system call whose failure the crate attributes in the error it returns.
+///
+/// Errors keep the code returned by the failing call and carry the call as the source of their error information, from
+/// which [`Syscall::of`] recovers it. Their message is prefixed with `failed in <name>: ` as well, so that logs name
+/// the call, e.g. "failed in NtUserGetInputEvent: Access is denied.". The calls are made by:
+///
+/// - [`Syscall::NtUserGetInputEvent`]: arming a wait, and [`set_wake_mask`](crate::set_wake_mask).
+/// - [`Syscall::NtUserSetWaitForQueueAttach`]: arming a wait with `MWMO_QUEUEATTACH`.
+/// - [`Syscall::NtUserGetQueueStatus`]: checking the queue before arming a wait, and reading the queue status.
+/// - [`Syscall::NtUserClearWakeMask`]: [`clear_wake_mask`](crate::clear_wake_mask).
+/// - [`Syscall::CreateThreadpoolWait`]: arming a wait with [`Backend::Threadpool`](crate::Backend::Threadpool), once
+///   the retries are exhausted and the cached wait is unavailable.
+/// - [`Syscall::CreateThreadpoolTimer`]: [`queue_status_stream`](crate::queue_status_stream).
+/// - [`Syscall::PeekMessageW`]: [`MessageIterator::drain_into`](crate::MessageIterator::drain_into).
+///
+/// Errors that don't come from a system call, such as rejected flags, aren't attributed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Syscall {
+    NtUserGetInputEvent,
+    NtUserSetWaitForQueueAttach,
+    /// `NtUserGetQueueStatusReadonly`, or `NtUserGetQueueStatus` where it's unavailable.
+    NtUserGetQueueStatus,
+    NtUserClearWakeMask,
+    CreateThreadpoolWait,
+    CreateThreadpoolTimer,
+    PeekMessageW,
+}
+
+impl Syscall {
+    const ALL: [Self; 7] = [
+        Self::NtUserGetInputEvent,
+        Self::NtUserSetWaitForQueueAttach,
+        Self::NtUserGetQueueStatus,
+        Self::NtUserClearWakeMask,
+        Self::CreateThreadpoolWait,
+        Self::CreateThreadpoolTimer,
+        Self::PeekMessageW,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::NtUserGetInputEvent => "NtUserGetInputEvent",
+            Self::NtUserSetWaitForQueueAttach => "NtUserSetWaitForQueueAttach",
+            Self::NtUserGetQueueStatus => "NtUserGetQueueStatus",
+            Self::NtUserClearWakeMask => "NtUserClearWakeMask",
+            Self::CreateThreadpoolWait => "CreateThreadpoolWait",
+            Self::CreateThreadpoolTimer => "CreateThreadpoolTimer",
+            Self::PeekMessageW => "PeekMessageW",
+        }
+    }
+
+    /// Returns the system call `error` is attributed to, if it was returned by this crate for a failed call.
+    ///
+    /// The message isn't looked at, so an error that merely reads like an attributed one isn't attributed.
+    pub fn of(error: &windows::core::Error) -> Option<Self> {
+        let info = unsafe { IUnknown::from_raw_borrowed(&error.as_ptr()) }?
+            .cast::<IErrorInfo>()
+            .ok()?;
+        let source = unsafe { info.GetSource() }.ok()?.to_string();
+
+        Self::ALL
+            .into_iter()
+            .find(|syscall| syscall.name() == source)
+    }
+
+    /// Attributes `error` to this call, keeping its code.
+    pub(crate) fn error(self, error: impl Into<windows::core::Error>) -> windows::core::Error {
+        let error = error.into();
+        let message = HSTRING::from(format!("failed in {}: {}", self.name(), error.message()));
+        let source = HSTRING::from(self.name());
+
+        let attributed = unsafe {
+            CreateErrorInfo().and_then(|info| {
+                info.SetDescription(PCWSTR(message.as_ptr()))?;
+                info.SetSource(PCWSTR(source.as_ptr()))?;
+                SetErrorInfo(0, &info.cast::<IErrorInfo>()?)
+            })
+        };
+
+        match attributed {
+            // Converting the code picks up the error information set for the thread above.
+            Ok(()) => windows::core::Error::from(error.code()),
+            // Without error information, only the message names the call.
+            Err(_) => windows::core::Error::new(error.code(), message.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::Foundation::E_ACCESSDENIED;
+
+    use super::Syscall;
+
+    #[test]
+    fn every_syscall_is_attributed() {
+        for syscall in Syscall::ALL {
+            let error = syscall.error(E_ACCESSDENIED);
+
+            assert_eq!(Syscall::of(&error), Some(syscall));
+            assert_eq!(error.code(), E_ACCESSDENIED);
+            assert!(!error.message().is_empty());
+        }
+    }
+}
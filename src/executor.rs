@@ -0,0 +1,56 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+};
+
+use windows::{
+    Win32::{
+        Foundation::HANDLE,
+        System::Threading::{CreateEventW, SetEvent},
+    },
+    core::Owned,
+};
+
+/// Signals an auto-reset event when woken.
+struct EventWaker(Owned<HANDLE>);
+
+// SAFETY: Event handles can be signaled from any thread.
+unsafe impl Send for EventWaker {}
+unsafe impl Sync for EventWaker {}
+
+impl Wake for EventWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        unsafe { SetEvent(*self.0) }.unwrap();
+    }
+}
+
+/// Runs `future` to completion on the current thread.
+///
+/// Between polls, `wait` is called with an event that is signaled once the future is woken and has to block until
+/// then.
+pub(crate) fn block_on_event<F: Future>(
+    future: F,
+    mut wait: impl FnMut(HANDLE) -> windows::core::Result<()>,
+) -> windows::core::Result<F::Output> {
+    let event = Arc::new(EventWaker(unsafe {
+        Owned::new(CreateEventW(None, false, false, None)?)
+    }));
+    let waker = Waker::from(event.clone());
+    let mut context = Context::from_waker(&waker);
+
+    let mut future = pin!(future);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return Ok(output);
+        }
+
+        wait(*event.0)?;
+    }
+}
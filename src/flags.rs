@@ -0,0 +1,95 @@
+use windows::Win32::UI::WindowsAndMessaging::{
+    MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, MWMO_INPUTAVAILABLE, QS_ALLEVENTS, QS_ALLINPUT,
+    QS_ALLPOSTMESSAGE, QS_HOTKEY, QS_INPUT, QS_KEY, QS_MOUSE, QS_MOUSEBUTTON, QS_MOUSEMOVE,
+    QS_PAINT, QS_POINTER, QS_POSTMESSAGE, QS_RAWINPUT, QS_SENDMESSAGE, QS_TIMER, QS_TOUCH,
+    QUEUE_STATUS_FLAGS,
+};
+
+use crate::MWMO_QUEUEATTACH;
+
+/// Builds the queue status flags selecting the kinds of messages to wait for.
+///
+/// Every function taking queue status flags accepts this as well as the raw `QS_*` constants.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct QueueStatusFlags(u32);
+
+macro_rules! queue_status_flags {
+    ($($name:ident => $flag:ident),* $(,)?) => {
+        impl QueueStatusFlags {
+            $(
+                #[doc = concat!("Adds `", stringify!($flag), "`.")]
+                pub const fn $name(self) -> Self {
+                    Self(self.0 | $flag.0)
+                }
+            )*
+        }
+    };
+}
+
+queue_status_flags! {
+    key => QS_KEY,
+    mouse_move => QS_MOUSEMOVE,
+    mouse_button => QS_MOUSEBUTTON,
+    mouse => QS_MOUSE,
+    post_message => QS_POSTMESSAGE,
+    all_post_message => QS_ALLPOSTMESSAGE,
+    timer => QS_TIMER,
+    paint => QS_PAINT,
+    send_message => QS_SENDMESSAGE,
+    hotkey => QS_HOTKEY,
+    raw_input => QS_RAWINPUT,
+    touch => QS_TOUCH,
+    pointer => QS_POINTER,
+    input => QS_INPUT,
+    all_events => QS_ALLEVENTS,
+    all_input => QS_ALLINPUT,
+}
+
+impl QueueStatusFlags {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    pub const fn build(self) -> QUEUE_STATUS_FLAGS {
+        QUEUE_STATUS_FLAGS(self.0)
+    }
+}
+
+impl From<QueueStatusFlags> for QUEUE_STATUS_FLAGS {
+    fn from(value: QueueStatusFlags) -> Self {
+        value.build()
+    }
+}
+
+/// Builds the wait flags controlling how to wait.
+///
+/// Only the flags supported by this crate can be set, so unlike the raw `MWMO_*` constants, these are never rejected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WaitFlags(u32);
+
+impl WaitFlags {
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Adds `MWMO_INPUTAVAILABLE`, completing the wait if matching messages are queued, even if they have already been
+    /// seen.
+    pub const fn input_available(self) -> Self {
+        Self(self.0 | MWMO_INPUTAVAILABLE.0)
+    }
+
+    /// Adds [`MWMO_QUEUEATTACH`].
+    pub const fn queue_attach(self) -> Self {
+        Self(self.0 | MWMO_QUEUEATTACH.0)
+    }
+
+    pub const fn build(self) -> MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS {
+        MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS(self.0)
+    }
+}
+
+impl From<WaitFlags> for MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS {
+    fn from(value: WaitFlags) -> Self {
+        value.build()
+    }
+}
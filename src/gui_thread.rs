@@ -0,0 +1,309 @@
+use std::{
+    cell::RefCell,
+    future::poll_fn,
+    sync::{Arc, Mutex, mpsc},
+    task::{Poll, Waker},
+    thread::JoinHandle,
+};
+
+use windows::{
+    Win32::{
+        Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, POINT, WAIT_OBJECT_0, WPARAM},
+        System::{
+            LibraryLoader::GetModuleHandleW,
+            Threading::{GetCurrentThreadId, INFINITE, WaitForSingleObject},
+        },
+        UI::WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DestroyWindow, HWND_MESSAGE, InSendMessage, MSG,
+            MWMO_INPUTAVAILABLE, PostThreadMessageW, QS_ALLINPUT, RegisterClassExW,
+            WINDOW_EX_STYLE, WINDOW_STYLE, WM_QUIT, WNDCLASSEXW,
+        },
+    },
+    core::{PCWSTR, w},
+};
+
+use crate::{MessageWaiter, dispatch_message, executor::block_on_event};
+
+const WINDOW_CLASS_NAME: PCWSTR = w!("async-messages GuiThread");
+
+/// The atom of the window class, registered by the first GUI thread and never unregistered.
+static WINDOW_CLASS: Mutex<u16> = Mutex::new(0);
+
+thread_local! {
+    /// The channel of the GUI thread running on this thread, for the window procedure.
+    static SENT_MESSAGE_CHANNEL: RefCell<Option<Arc<Channel>>> = const { RefCell::new(None) };
+}
+
+/// An owned copy of a [`MSG`] that can be sent to other threads.
+///
+/// The window handle is kept as an integer, as handles aren't `Send`. It's only meaningful to the thread that owns the
+/// window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageSnapshot {
+    pub hwnd: usize,
+    pub message: u32,
+    pub wparam: WPARAM,
+    pub lparam: LPARAM,
+    pub time: u32,
+    pub pt: POINT,
+}
+
+impl MessageSnapshot {
+    pub fn hwnd(&self) -> HWND {
+        HWND(self.hwnd as _)
+    }
+}
+
+impl From<&MSG> for MessageSnapshot {
+    fn from(msg: &MSG) -> Self {
+        Self {
+            hwnd: msg.hwnd.0 as _,
+            message: msg.message,
+            wparam: msg.wParam,
+            lparam: msg.lParam,
+            time: msg.time,
+            pt: msg.pt,
+        }
+    }
+}
+
+#[derive(Default)]
+struct ChannelState {
+    messages: Vec<MessageSnapshot>,
+    waker: Option<Waker>,
+    closed: bool,
+}
+
+/// Forwards batches of snapshots from the GUI thread to the owner of the [`GuiThread`].
+#[derive(Default)]
+struct Channel(Mutex<ChannelState>);
+
+impl Channel {
+    fn send(&self, messages: impl IntoIterator<Item = MessageSnapshot>) {
+        let mut state = self.0.lock().unwrap();
+        state.messages.extend(messages);
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.closed = true;
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// A dedicated thread owning a message-only window and running a message loop.
+///
+/// This is meant for code that needs a message loop without having a GUI, e.g. to receive notifications registered
+/// with `RegisterDeviceNotification` on [`GuiThread::hwnd`]. Messages are dispatched on the GUI thread, and snapshots
+/// of them are forwarded to [`GuiThread::recv_messages`].
+///
+/// Messages sent to the window from other threads, like `WM_DEVICECHANGE`, never enter the queue and are forwarded
+/// from the window's procedure instead. Their snapshots have a zero `time` and `pt`, as sent messages carry neither.
+///
+/// Dropping the handle stops the thread and waits for it to exit.
+pub struct GuiThread {
+    thread_id: u32,
+    hwnd: usize,
+    channel: Arc<Channel>,
+    thread: Option<JoinHandle<windows::core::Result<()>>>,
+}
+
+impl GuiThread {
+    pub fn spawn() -> windows::core::Result<Self> {
+        let channel = Arc::new(Channel::default());
+        let (started_tx, started_rx) = mpsc::channel();
+
+        let thread = std::thread::spawn({
+            let channel = channel.clone();
+            move || {
+                let result = run(channel.clone(), started_tx);
+                channel.close();
+                result
+            }
+        });
+
+        match started_rx.recv() {
+            Ok((thread_id, hwnd)) => Ok(Self {
+                thread_id,
+                hwnd,
+                channel,
+                thread: Some(thread),
+            }),
+            // The thread failed to start up and dropped the sender.
+            Err(_) => Err(thread.join().unwrap().unwrap_err()),
+        }
+    }
+
+    /// The message-only window owned by the GUI thread.
+    pub fn hwnd(&self) -> HWND {
+        HWND(self.hwnd as _)
+    }
+
+    pub fn thread_id(&self) -> u32 {
+        self.thread_id
+    }
+
+    /// Waits for the messages drained by the GUI thread since the last call.
+    ///
+    /// Resolves to `None` once the GUI thread has exited and every message has been received.
+    pub async fn recv_messages(&mut self) -> Option<Vec<MessageSnapshot>> {
+        poll_fn(|cx| {
+            let mut state = self.channel.0.lock().unwrap();
+
+            if !state.messages.is_empty() {
+                Poll::Ready(Some(std::mem::take(&mut state.messages)))
+            } else if state.closed {
+                Poll::Ready(None)
+            } else {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Stops the GUI thread and waits for it to exit, returning the error that stopped it early, if any.
+    ///
+    /// If the GUI thread panicked, the panic is resumed on the calling thread.
+    pub fn join(mut self) -> windows::core::Result<()> {
+        self.stop()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+
+    fn stop(&mut self) -> std::thread::Result<windows::core::Result<()>> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(Ok(()));
+        };
+
+        // PostQuitMessage only affects the calling thread, so WM_QUIT is posted directly. This fails if the thread has
+        // already exited, which is fine.
+        unsafe {
+            _ = PostThreadMessageW(self.thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+        }
+
+        thread.join()
+    }
+}
+
+impl Drop for GuiThread {
+    fn drop(&mut self) {
+        // Neither an error nor a panic of the GUI thread may escape a drop, so both are discarded.
+        _ = self.stop();
+    }
+}
+
+fn register_window_class(instance: HINSTANCE) -> windows::core::Result<()> {
+    let mut window_class = WINDOW_CLASS.lock().unwrap();
+    if *window_class != 0 {
+        return Ok(());
+    }
+
+    let atom = unsafe {
+        RegisterClassExW(&WNDCLASSEXW {
+            cbSize: size_of::<WNDCLASSEXW>() as _,
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: WINDOW_CLASS_NAME,
+            ..Default::default()
+        })
+    };
+
+    if atom == 0 {
+        return Err(windows::core::Error::from_win32());
+    }
+
+    *window_class = atom;
+    Ok(())
+}
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    // Posted messages are forwarded by the message loop once they're dispatched.
+    if unsafe { InSendMessage() }.as_bool() {
+        SENT_MESSAGE_CHANNEL.with_borrow(|channel| {
+            if let Some(channel) = channel {
+                channel.send([MessageSnapshot {
+                    hwnd: hwnd.0 as _,
+                    message,
+                    wparam,
+                    lparam,
+                    time: 0,
+                    pt: POINT::default(),
+                }]);
+            }
+        });
+    }
+
+    unsafe { DefWindowProcW(hwnd, message, wparam, lparam) }
+}
+
+fn run(channel: Arc<Channel>, started: mpsc::Sender<(u32, usize)>) -> windows::core::Result<()> {
+    let instance = unsafe { GetModuleHandleW(None) }.map(|module| HINSTANCE(module.0))?;
+    register_window_class(instance)?;
+
+    SENT_MESSAGE_CHANNEL.set(Some(channel.clone()));
+
+    let hwnd = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE(0),
+            WINDOW_CLASS_NAME,
+            w!("async-messages"),
+            WINDOW_STYLE(0),
+            0,
+            0,
+            0,
+            0,
+            Some(HWND_MESSAGE),
+            None,
+            Some(instance),
+            None,
+        )?
+    };
+
+    _ = started.send((unsafe { GetCurrentThreadId() }, hwnd.0 as _));
+
+    let result = block_on_event(
+        async {
+            let mut waiter = MessageWaiter::new(QS_ALLINPUT, MWMO_INPUTAVAILABLE)?;
+
+            loop {
+                let mut batch = Vec::new();
+
+                for msg in waiter.wait().await? {
+                    if msg.message == WM_QUIT {
+                        channel.send(batch);
+                        return Ok(());
+                    }
+
+                    dispatch_message(&msg, |_| {});
+                    batch.push(MessageSnapshot::from(&msg));
+                }
+
+                channel.send(batch);
+            }
+        },
+        |event| {
+            if unsafe { WaitForSingleObject(event, INFINITE) } == WAIT_OBJECT_0 {
+                Ok(())
+            } else {
+                Err(windows::core::Error::from_win32())
+            }
+        },
+    )
+    .and_then(|result| result);
+
+    let destroyed = unsafe { DestroyWindow(hwnd) };
+    SENT_MESSAGE_CHANNEL.set(None);
+    result.and(destroyed)
+}
@@ -0,0 +1,56 @@
+use std::num::NonZeroUsize;
+
+use windows::Win32::UI::WindowsAndMessaging::MSG;
+
+use crate::{MessageIterator, MessageSource, ThreadQueue};
+
+/// Lends the messages of a batch as slices of a reused buffer, created by [`MessageIterator::lend_into`] or
+/// [`MessageWaiter::wait_lending`](crate::MessageWaiter::wait_lending).
+///
+/// Every call to [`LendingMessages::next`] clears the buffer, removes up to the chunk size of messages into it and
+/// lends them out. The buffer is never shrunk, so once it has grown to the chunk size, draining doesn't allocate
+/// anymore, no matter how many chunks or batches go through it.
+///
+/// This can't implement [`Iterator`], as a slice borrows the buffer and is invalidated by the next call to
+/// [`LendingMessages::next`]; the borrow checker rejects holding on to it. Copy out the messages that need to outlive
+/// the chunk.
+pub struct LendingMessages<'a, S = ThreadQueue> {
+    messages: MessageIterator<'a, S>,
+    buffer: &'a mut Vec<MSG>,
+    chunk_size: NonZeroUsize,
+}
+
+impl<'a, S: MessageSource> LendingMessages<'a, S> {
+    pub(crate) fn new(
+        messages: MessageIterator<'a, S>,
+        buffer: &'a mut Vec<MSG>,
+        chunk_size: NonZeroUsize,
+    ) -> Self {
+        buffer.clear();
+        buffer.reserve(chunk_size.get());
+
+        Self {
+            messages,
+            buffer,
+            chunk_size,
+        }
+    }
+
+    /// Removes the next chunk of messages and lends it out, or returns `None` once the batch is drained.
+    ///
+    /// Like iterating the [`MessageIterator`], failing to retrieve a message ends the batch.
+    #[expect(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&[MSG]> {
+        self.buffer.clear();
+        self.buffer
+            .extend(self.messages.by_ref().take(self.chunk_size.get()));
+
+        (!self.buffer.is_empty()).then_some(&self.buffer[..])
+    }
+
+    /// The iterator the chunks are drained from, e.g. for its
+    /// [`mouse_move_stats`](MessageIterator::mouse_move_stats).
+    pub fn messages(&self) -> &MessageIterator<'a, S> {
+        &self.messages
+    }
+}
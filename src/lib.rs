@@ -2,8 +2,105 @@
 #![deny(unused)]
 #![deny(clippy::missing_safety_doc)]
 
+mod attach;
 mod bindings;
+#[cfg(feature = "tokio")]
+mod cancel;
+mod diagnostics;
+mod dispatch;
+mod error;
+mod executor;
+mod flags;
+mod gui_thread;
+#[cfg(feature = "lending")]
+mod lending;
+mod message;
+mod message_source;
 mod msg_future;
+mod queue_status;
+mod reactor;
+mod signal;
+#[cfg(feature = "tokio")]
+mod source;
+mod sta;
+mod waiter;
+mod wake_mask;
 
+pub use attach::AttachedInput;
+pub use attach::wait_for_attached_input;
+#[cfg(feature = "tokio")]
+pub use cancel::WaitOutcome;
+#[cfg(feature = "tokio")]
+pub use cancel::wait_for_messages_until;
+pub use diagnostics::WaitState;
+pub use diagnostics::WaitStateSnapshot;
+pub use dispatch::QuitCode;
+pub use dispatch::dispatch_message;
+pub use dispatch::dispatch_message_with_result;
+pub use dispatch::drain_until_empty;
+pub use dispatch::for_each_message;
+pub use dispatch::process_sent_messages;
+pub use dispatch::run_message_loop;
+pub use dispatch::run_message_loop_with;
+pub use dispatch::run_message_loop_with_results;
+pub use dispatch::wait_for_quit;
+pub use error::Syscall;
+pub use flags::QueueStatusFlags;
+pub use flags::WaitFlags;
+pub use gui_thread::GuiThread;
+pub use gui_thread::MessageSnapshot;
+#[cfg(feature = "lending")]
+pub use lending::LendingMessages;
+pub use message::Message;
+pub use message::TypedMessageIterator;
+pub use message_source::MessageSource;
+#[cfg(feature = "test-util")]
+pub use message_source::ScriptedMessages;
+pub use message_source::ThreadQueue;
+pub use msg_future::DEFAULT_CALLBACK_TIMEOUT;
+pub use msg_future::InputEventFuture;
 pub use msg_future::MWMO_QUEUEATTACH;
+pub use msg_future::MessageIterator;
+pub use msg_future::OverlapPolicy;
+pub use msg_future::PAINT_RECHECK_INTERVAL;
+pub use msg_future::SUPPORTED_QUEUE_STATUS_FLAGS;
+pub use msg_future::TryOrWait;
+pub use msg_future::leaked_waits;
+pub use msg_future::reap_detached;
+pub use msg_future::try_or_wait;
 pub use msg_future::wait_for_messages;
+pub use queue_status::DEFAULT_MIN_INTERVAL;
+pub use queue_status::MouseMoveStats;
+pub use queue_status::QueueStatus;
+pub use queue_status::QueueStatusBackend;
+pub use queue_status::QueueStatusStream;
+pub use queue_status::queue_status_backend;
+pub use queue_status::queue_status_stream;
+pub use reactor::Backend;
+pub use reactor::block_on_reactor;
+pub use signal::MessageSignal;
+pub use signal::Subscription;
+#[cfg(feature = "tokio")]
+pub use source::MessageReceiver;
+#[cfg(feature = "tokio")]
+pub use source::spawn_message_source;
+pub use sta::block_on_sta;
+pub use waiter::MaskChangePolicy;
+pub use waiter::MessageWaiter;
+pub use waiter::MessageWaiterBuilder;
+pub use waiter::next_message;
+pub use waiter::recv_one_timeout;
+pub use waiter::wait_for_hotkey;
+pub use wake_mask::clear_wake_mask;
+pub use wake_mask::set_wake_mask;
+
+#[cfg(feature = "macros")]
+pub use async_messages_macros::message_loop;
+
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::dispatch::translate_and_dispatch;
+    pub use windows::Win32::UI::WindowsAndMessaging::{MWMO_NONE, QS_ALLINPUT};
+    pub use windows::core::Result;
+}
@@ -0,0 +1,91 @@
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, POINT, WPARAM},
+    UI::WindowsAndMessaging::{MSG, WM_QUIT},
+};
+
+use crate::{MessageIterator, MessageSource, ThreadQueue};
+
+/// A retrieved message.
+///
+/// This is a thin wrapper around [`MSG`] with accessors for its fields. Use [`Message::as_raw`] to pass it to
+/// functions expecting a [`MSG`], such as `DispatchMessageW` or [`dispatch_message`](crate::dispatch_message).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(transparent)]
+pub struct Message(pub MSG);
+
+impl Message {
+    /// The message identifier, e.g. `WM_PAINT`.
+    pub fn id(&self) -> u32 {
+        self.0.message
+    }
+
+    pub fn is_quit(&self) -> bool {
+        self.0.message == WM_QUIT
+    }
+
+    /// The window the message is destined for, or `None` for thread messages.
+    pub fn hwnd(&self) -> Option<HWND> {
+        (!self.0.hwnd.0.is_null()).then_some(self.0.hwnd)
+    }
+
+    pub fn wparam(&self) -> WPARAM {
+        self.0.wParam
+    }
+
+    pub fn lparam(&self) -> LPARAM {
+        self.0.lParam
+    }
+
+    /// The time the message was posted, in milliseconds since the system started.
+    pub fn time(&self) -> u32 {
+        self.0.time
+    }
+
+    /// The cursor position in screen coordinates when the message was posted.
+    pub fn point(&self) -> POINT {
+        self.0.pt
+    }
+
+    pub fn as_raw(&self) -> &MSG {
+        &self.0
+    }
+
+    pub fn into_raw(self) -> MSG {
+        self.0
+    }
+}
+
+impl From<MSG> for Message {
+    fn from(msg: MSG) -> Self {
+        Self(msg)
+    }
+}
+
+impl From<Message> for MSG {
+    fn from(message: Message) -> Self {
+        message.0
+    }
+}
+
+impl AsRef<MSG> for Message {
+    fn as_ref(&self) -> &MSG {
+        &self.0
+    }
+}
+
+/// An iterator over retrieved messages yielding [`Message`]s, created by [`MessageIterator::typed`].
+pub struct TypedMessageIterator<'a, S = ThreadQueue>(MessageIterator<'a, S>);
+
+impl<'a, S> TypedMessageIterator<'a, S> {
+    pub(crate) fn new(iterator: MessageIterator<'a, S>) -> Self {
+        Self(iterator)
+    }
+}
+
+impl<S: MessageSource> Iterator for TypedMessageIterator<'_, S> {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(Message)
+    }
+}
@@ -0,0 +1,122 @@
+#[cfg(feature = "test-util")]
+use std::collections::VecDeque;
+use std::mem::MaybeUninit;
+
+use windows::Win32::UI::WindowsAndMessaging::{
+    MSG, PEEK_MESSAGE_REMOVE_TYPE, PM_REMOVE, PeekMessageW, WM_PAINT,
+};
+
+use crate::{Syscall, msg_future::record_paint_retrieved};
+
+/// Where a [`MessageIterator`](crate::MessageIterator) retrieves its messages from.
+///
+/// The iterator only ever retrieves messages through this trait, so its draining logic can be exercised with a scripted
+/// sequence of messages instead of a real message queue. The default, [`ThreadQueue`], calls `PeekMessageW`.
+pub trait MessageSource {
+    /// Returns the first message within `min..=max`, where `0, 0` matches every message, or `None` if there is none.
+    /// The message is removed if `flags` contains `PM_REMOVE`.
+    fn peek_message(
+        &mut self,
+        min: u32,
+        max: u32,
+        flags: PEEK_MESSAGE_REMOVE_TYPE,
+    ) -> windows::core::Result<Option<MSG>>;
+}
+
+impl<S: MessageSource + ?Sized> MessageSource for &mut S {
+    fn peek_message(
+        &mut self,
+        min: u32,
+        max: u32,
+        flags: PEEK_MESSAGE_REMOVE_TYPE,
+    ) -> windows::core::Result<Option<MSG>> {
+        (**self).peek_message(min, max, flags)
+    }
+}
+
+/// The calling thread's message queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ThreadQueue;
+
+impl MessageSource for ThreadQueue {
+    fn peek_message(
+        &mut self,
+        min: u32,
+        max: u32,
+        flags: PEEK_MESSAGE_REMOVE_TYPE,
+    ) -> windows::core::Result<Option<MSG>> {
+        let mut msg = MaybeUninit::uninit();
+
+        match unsafe { PeekMessageW(msg.as_mut_ptr(), None, min, max, flags) }.0 {
+            0 => Ok(None),
+            -1 => Err(Syscall::PeekMessageW.error(windows::core::Error::from_win32())),
+            _ => {
+                // SAFETY: PeekMessageW initialized the message.
+                let msg = unsafe { msg.assume_init() };
+
+                // Retrieving a WM_PAINT doesn't remove it, see InputEventFuture.
+                if msg.message == WM_PAINT && flags.0 & PM_REMOVE.0 != 0 {
+                    record_paint_retrieved();
+                }
+
+                Ok(Some(msg))
+            }
+        }
+    }
+}
+
+/// A scripted sequence of messages, for testing code built on [`MessageIterator`](crate::MessageIterator) without a
+/// message queue.
+///
+/// Messages are handed out in order and honor the message range like `PeekMessageW` does. The `PM_QS_*` qualifiers
+/// are ignored, and sent messages can't be scripted. Pass `&mut ScriptedMessages` to inspect the messages that are
+/// left once the iterator is done.
+#[cfg(feature = "test-util")]
+#[derive(Clone, Debug, Default)]
+pub struct ScriptedMessages {
+    messages: VecDeque<MSG>,
+}
+
+#[cfg(feature = "test-util")]
+impl ScriptedMessages {
+    pub fn new(messages: impl IntoIterator<Item = MSG>) -> Self {
+        Self {
+            messages: messages.into_iter().collect(),
+        }
+    }
+
+    /// Queues another message behind the scripted ones.
+    pub fn push(&mut self, msg: MSG) {
+        self.messages.push_back(msg);
+    }
+
+    /// The messages that haven't been removed yet, in order.
+    pub fn remaining(&self) -> &VecDeque<MSG> {
+        &self.messages
+    }
+}
+
+#[cfg(feature = "test-util")]
+impl MessageSource for ScriptedMessages {
+    fn peek_message(
+        &mut self,
+        min: u32,
+        max: u32,
+        flags: PEEK_MESSAGE_REMOVE_TYPE,
+    ) -> windows::core::Result<Option<MSG>> {
+        let unfiltered = min == 0 && max == 0;
+        let Some(index) = self
+            .messages
+            .iter()
+            .position(|msg| unfiltered || (min..=max).contains(&msg.message))
+        else {
+            return Ok(None);
+        };
+
+        if flags.0 & PM_REMOVE.0 != 0 {
+            Ok(self.messages.remove(index))
+        } else {
+            Ok(Some(self.messages[index]))
+        }
+    }
+}
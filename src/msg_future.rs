@@ -1,58 +1,336 @@
 use std::{
+    cell::Cell,
     future::Future,
-    marker::{PhantomData, PhantomPinned},
-    mem::MaybeUninit,
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
+    ops::RangeInclusive,
     pin::Pin,
-    sync::atomic::{AtomicBool, AtomicU32, Ordering},
-    task::{Context, Poll, Waker},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    },
+    task::{Context, Poll, Waker, ready},
+    time::{Duration, Instant},
 };
 
-use helpers::ConfiguredInputEvent;
-use nt_user_call::functions::NtUserSetWaitForQueueAttach;
-use windows::{
-    Win32::{
-        Foundation::E_INVALIDARG,
-        System::Threading::{
-            CreateThreadpoolWait, PTP_CALLBACK_INSTANCE, PTP_WAIT, SetThreadpoolWait,
-            SetThreadpoolWaitEx, WaitForThreadpoolWaitCallbacks,
-        },
-        UI::WindowsAndMessaging::{
-            MSG, MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, MWMO_ALERTABLE, MWMO_WAITALL, PM_REMOVE,
-            PeekMessageW, QUEUE_STATUS_FLAGS,
-        },
+pub(crate) use helpers::invalidate_wake_masks;
+use helpers::{ConfiguredInputEvent, InputEventHandle};
+use wait_object::WaitObject;
+use windows::Win32::{
+    Foundation::{E_INVALIDARG, HANDLE},
+    System::Threading::{
+        PTP_CALLBACK_INSTANCE, PTP_WAIT, SetThreadpoolWait, SetThreadpoolWaitEx,
+        WaitForThreadpoolWaitCallbacks,
+    },
+    UI::WindowsAndMessaging::{
+        MSG, MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, MWMO_ALERTABLE, MWMO_INPUTAVAILABLE,
+        MWMO_WAITALL, PEEK_MESSAGE_REMOVE_TYPE, PM_NOREMOVE, PM_NOYIELD, PM_REMOVE, PeekMessageW,
+        QS_ALLEVENTS, QS_ALLINPUT, QS_ALLPOSTMESSAGE, QS_MOUSEMOVE, QS_PAINT, QS_POINTER, QS_TOUCH,
+        QUEUE_STATUS_FLAGS, WM_MOUSEMOVE,
     },
-    core::Owned,
 };
 
-use crate::bindings::NtUserGetQueueStatusReadonly;
+use crate::{
+    MessageSource, MouseMoveStats, QueueStatus, Syscall, ThreadQueue, TypedMessageIterator,
+    bindings::NtUserGetQueueStatusReadonly,
+    diagnostics::{WaitState, WaitStateSnapshot},
+    dispatch::process_sent_messages,
+    queue_status::{RecheckTimer, relative_due_time},
+    reactor::{self, Backend},
+};
 
 pub const MWMO_QUEUEATTACH: MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS =
     MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS(0x0008);
 
-const fn make_dword(low: u16, high: u16) -> u32 {
+/// The documented `QS_*` flags. Everything else, e.g. the internal `QS_EVENT`, is rejected, as the input event may
+/// never be signaled for it.
+///
+/// `QS_TOUCH` and `QS_POINTER` are listed explicitly, as the bindings' `QS_ALLINPUT` predates them.
+pub const SUPPORTED_QUEUE_STATUS_FLAGS: QUEUE_STATUS_FLAGS =
+    QUEUE_STATUS_FLAGS(QS_ALLINPUT.0 | QS_ALLPOSTMESSAGE.0 | QS_TOUCH.0 | QS_POINTER.0);
+
+/// How often creating a threadpool wait is retried before falling back to the thread's cached wait.
+pub(crate) const DEFAULT_CREATE_WAIT_RETRIES: u32 = 2;
+
+/// How long dropping a future waits for a running threadpool callback before leaking the wait instead.
+pub const DEFAULT_CALLBACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long waiting for a running threadpool callback yields the thread before it starts to sleep. The callback
+/// usually finishes within microseconds, while a sleep takes at least a timer tick.
+const CALLBACK_YIELD_DURATION: Duration = Duration::from_millis(1);
+
+static LEAKED_WAITS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns how many waits have been leaked process-wide because their threadpool callback didn't finish within the
+/// callback timeout, see [`MessageWaiterBuilder::callback_timeout`](crate::MessageWaiterBuilder::callback_timeout).
+///
+/// This is meant for diagnostics, e.g. to report a stuck wake hook or waker; a leak doesn't surface as an error
+/// anywhere else.
+pub fn leaked_waits() -> usize {
+    LEAKED_WAITS.load(Ordering::Relaxed)
+}
+
+fn record_leaked_wait() {
+    LEAKED_WAITS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How often a wait armed without `QS_PAINT` checks whether the update region has been validated, see
+/// [`InputEventFuture`].
+pub const PAINT_RECHECK_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Packs the queue status flags into the low word and the wait flags into the high word.
+///
+/// Taking `u16`s makes truncation impossible here; [`narrow_flags`] is the only place where
+/// flags are narrowed.
+pub(crate) const fn make_dword(low: u16, high: u16) -> u32 {
     (low as u32) | ((high as u32) << 16)
 }
 
+// Every documented combination of flags has to survive the packing.
+const _: () = assert!(QS_ALLINPUT.0 <= u16::MAX as u32);
+const _: () = assert!(QS_ALLEVENTS.0 <= u16::MAX as u32);
+const _: () = assert!(
+    (MWMO_INPUTAVAILABLE.0 | MWMO_QUEUEATTACH.0 | MWMO_ALERTABLE.0 | MWMO_WAITALL.0)
+        <= u16::MAX as u32
+);
+
+/// A hook that runs on the threadpool thread right after a wait completes.
+pub(crate) type WakeHook = Arc<Mutex<dyn FnMut() + Send>>;
+
+/// What arming a wait does if another wait is already armed on the same thread.
+///
+/// The wake mask and the queue's wait completion packet are per-thread state, so overlapping waits on the same thread
+/// interfere with each other: whichever wait is released first clears the wake mask and reassociates the packet for
+/// all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OverlapPolicy {
+    /// Arm the wait anyway.
+    #[default]
+    Allow,
+    /// Fail polling with `E_ILLEGAL_METHOD_CALL`.
+    Error,
+    /// Panic when polled.
+    Panic,
+}
+
+/// Per-future settings that don't affect which messages are waited for.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WaitOptions {
+    pub create_wait_retries: u32,
+    pub message_filter: MessageFilter,
+    pub callback_timeout: Duration,
+    pub manage_completion_packet: bool,
+    pub eager_drain: bool,
+    pub overlap_policy: OverlapPolicy,
+    pub backend: Backend,
+    pub spin_budget: Option<Duration>,
+    pub sent_only: bool,
+    pub external_input_event: Option<InputEventHandle>,
+    pub track_mouse_moves: bool,
+    pub deadline: Option<Instant>,
+}
+
+impl Default for WaitOptions {
+    fn default() -> Self {
+        Self {
+            create_wait_retries: DEFAULT_CREATE_WAIT_RETRIES,
+            message_filter: MessageFilter::default(),
+            callback_timeout: DEFAULT_CALLBACK_TIMEOUT,
+            manage_completion_packet: true,
+            eager_drain: false,
+            overlap_policy: OverlapPolicy::Allow,
+            backend: Backend::Threadpool,
+            spin_budget: None,
+            sent_only: false,
+            external_input_event: None,
+            track_mouse_moves: false,
+            deadline: None,
+        }
+    }
+}
+
+impl WaitOptions {
+    /// Waits on `input_event` instead of configuring the thread's input event, see
+    /// [`InputEventFuture::from_raw_parts`].
+    pub fn set_external_input_event(&mut self, input_event: HANDLE) -> windows::core::Result<()> {
+        self.external_input_event = Some(InputEventHandle::from_raw(input_event)?);
+        Ok(())
+    }
+
+    /// Returns whether the wait has a deadline that has passed.
+    pub fn deadline_passed(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Returns the iterator a resolved wait hands out.
+    pub fn messages(&self) -> windows::core::Result<MessageIterator<'static>> {
+        // Retrieving messages clears the "added" bits, so they have to be read before draining eagerly.
+        let mouse_move_reported = if self.track_mouse_moves {
+            let status = QueueStatus(queue_status(QS_MOUSEMOVE.0 as u16, 0)?);
+            Some(status.added().0 & QS_MOUSEMOVE.0 != 0)
+        } else {
+            None
+        };
+
+        let mut messages = if self.sent_only {
+            MessageIterator::sent_only()
+        } else if self.eager_drain {
+            MessageIterator::drain_now(self.message_filter)
+        } else {
+            MessageIterator::with_filter(self.message_filter)
+        };
+        messages.mouse_move_reported = mouse_move_reported;
+
+        Ok(messages)
+    }
+}
+
+/// The filter arguments passed to `PeekMessageW`: the `wMsgFilterMin`/`wMsgFilterMax` range, where `0, 0` matches
+/// every message, and the `PM_QS_*` qualifiers restricting the drained message categories.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct MessageFilter {
+    pub min: u32,
+    pub max: u32,
+    pub qualifiers: PEEK_MESSAGE_REMOVE_TYPE,
+}
+
+impl MessageFilter {
+    pub fn is_unfiltered(self) -> bool {
+        self == Self::default()
+    }
+
+    pub fn set_range(&mut self, range: RangeInclusive<u32>) {
+        self.min = *range.start();
+        self.max = *range.end();
+    }
+
+    /// Sets the `PM_QS_*` qualifiers. These live in the high word of the `PeekMessageW` flags, so anything in the low
+    /// word (`PM_REMOVE`, `PM_NOYIELD`) is rejected, as the crate controls those itself.
+    pub fn set_qualifiers(
+        &mut self,
+        qualifiers: PEEK_MESSAGE_REMOVE_TYPE,
+    ) -> windows::core::Result<()> {
+        if qualifiers.0 & 0xFFFF != 0 {
+            return Err(E_INVALIDARG.into());
+        }
+
+        self.qualifiers = qualifiers;
+        Ok(())
+    }
+
+    /// Checks whether a message passing the filter is queued, without removing it.
+    pub fn has_message(self) -> bool {
+        let mut msg = MaybeUninit::uninit();
+        unsafe {
+            PeekMessageW(
+                msg.as_mut_ptr(),
+                None,
+                self.min,
+                self.max,
+                PM_NOREMOVE | PM_NOYIELD | self.qualifiers,
+            )
+            .as_bool()
+        }
+    }
+}
+
 mod helpers {
-    use std::{ffi::c_void, ptr::NonNull};
+    use std::{cell::Cell, ffi::c_void, ptr::NonNull};
 
     use nt_user_call::functions::{
         NtUserCancelQueueEventCompletionPacket, NtUserClearWakeMask, NtUserGetInputEvent,
-        NtUserReassociateQueueEventCompletionPacket,
+        NtUserReassociateQueueEventCompletionPacket, NtUserSetWaitForQueueAttach,
     };
-    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Foundation::{E_ILLEGAL_METHOD_CALL, E_INVALIDARG, HANDLE};
+
+    use super::{MWMO_QUEUEATTACH, OverlapPolicy, make_dword};
+    use crate::Syscall;
 
-    use super::make_dword;
+    thread_local! {
+        static LAST_INPUT_EVENT: Cell<Option<InputEventHandle>> = const { Cell::new(None) };
+        static CONFIGURED: Cell<usize> = const { Cell::new(0) };
+        static WAKE_MASK_GENERATION: Cell<u64> = const { Cell::new(0) };
+    }
+
+    /// Records that the thread's wake mask may have been replaced or cleared by someone else, e.g. by a modal loop
+    /// calling `MsgWaitForMultipleObjectsEx`. Waits configured before are armed again when they're polled next.
+    pub fn invalidate_wake_masks() {
+        WAKE_MASK_GENERATION.set(WAKE_MASK_GENERATION.get() + 1);
+    }
+
+    /// The thread's input event, as returned by `NtUserGetInputEvent`.
+    ///
+    /// The event belongs to the thread's message queue and is handed out to every caller on that thread, so it must
+    /// never be closed. This is why the handle is not owning and deliberately doesn't implement `Free`.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct InputEventHandle(NonNull<c_void>);
+
+    impl InputEventHandle {
+        /// Returns `E_INVALIDARG` for a null handle.
+        pub fn from_raw(input_event: HANDLE) -> windows::core::Result<Self> {
+            NonNull::new(input_event.0)
+                .map(Self)
+                .ok_or_else(|| windows::core::Error::new(E_INVALIDARG, "the input event is null"))
+        }
+
+        pub fn as_raw(self) -> HANDLE {
+            HANDLE(self.0.as_ptr())
+        }
+    }
 
     /// Wraps the thread's input event and configures it so that it can be waited on.
     pub struct ConfiguredInputEvent {
-        input_event: NonNull<c_void>,
+        input_event: InputEventHandle,
+        manage_completion_packet: bool,
+        queue_attach: bool,
+        external: bool,
+        generation: u64,
     }
 
     impl ConfiguredInputEvent {
-        pub fn new(queue_status_flags: u16, wait_flags: u16) -> windows::core::Result<Self> {
+        /// Wraps an input event that has been configured by someone else, who also remains responsible for undoing
+        /// the configuration.
+        pub fn external(input_event: InputEventHandle) -> Self {
+            Self {
+                input_event,
+                manage_completion_packet: false,
+                queue_attach: false,
+                external: true,
+                generation: WAKE_MASK_GENERATION.get(),
+            }
+        }
+
+        pub fn new(
+            queue_status_flags: u16,
+            wait_flags: u16,
+            manage_completion_packet: bool,
+            overlap_policy: OverlapPolicy,
+        ) -> windows::core::Result<Self> {
+            if CONFIGURED.get() > 0 {
+                match overlap_policy {
+                    OverlapPolicy::Allow => {}
+                    OverlapPolicy::Error => {
+                        return Err(windows::core::Error::new(
+                            E_ILLEGAL_METHOD_CALL,
+                            "another wait is already armed on this thread",
+                        ));
+                    }
+                    OverlapPolicy::Panic => panic!("another wait is already armed on this thread"),
+                }
+            }
+
             let input_event =
-                unsafe { NtUserGetInputEvent(make_dword(queue_status_flags, wait_flags))? };
+                unsafe { NtUserGetInputEvent(make_dword(queue_status_flags, wait_flags)) }
+                    .map_err(|error| Syscall::NtUserGetInputEvent.error(error))?;
+
+            // SAFETY: `input_event` has been checked above
+            let input_event = InputEventHandle(unsafe { NonNull::new_unchecked(input_event.0) });
+
+            // The event is a per-thread resource, so every call on the same thread has to hand out the same handle.
+            if let Some(last_input_event) = LAST_INPUT_EVENT.replace(Some(input_event)) {
+                debug_assert_eq!(last_input_event, input_event);
+            }
 
             // Windows 10 introduced an I/O completion port into the message queue. This has the side effect that out
             // If the input event is associated with its wait completion packet, our wait won't get properly woken up.
@@ -60,27 +338,369 @@ mod helpers {
             // Cancel the wait completion packet and reassociate it when the wait is done.
             // We don't care about the result here - if the call isn't found, the OS doesn't have it, and the system call
             // itself does not return any information as to whether cancellation succeeded or not.
-            unsafe {
-                _ = NtUserCancelQueueEventCompletionPacket();
+            if manage_completion_packet {
+                unsafe {
+                    _ = NtUserCancelQueueEventCompletionPacket();
+                }
             }
 
-            Ok(Self {
-                // SAFETY: `input_event` has been checked above
-                input_event: unsafe { NonNull::new_unchecked(input_event.0) },
-            })
+            CONFIGURED.set(CONFIGURED.get() + 1);
+
+            let configured = Self {
+                input_event,
+                manage_completion_packet,
+                queue_attach: u32::from(wait_flags) & MWMO_QUEUEATTACH.0 != 0,
+                external: false,
+                generation: WAKE_MASK_GENERATION.get(),
+            };
+
+            // Makes attaching or detaching another thread's input queue signal the input event. Should this fail,
+            // dropping `configured` undoes everything above.
+            if configured.queue_attach {
+                unsafe { NtUserSetWaitForQueueAttach(true.into()) }
+                    .map_err(|error| Syscall::NtUserSetWaitForQueueAttach.error(error))?;
+            }
+
+            Ok(configured)
         }
 
-        pub fn as_raw(&self) -> HANDLE {
-            HANDLE(self.input_event.as_ptr())
+        pub fn handle(&self) -> InputEventHandle {
+            self.input_event
+        }
+
+        /// Returns whether the wake mask may have been replaced since the input event was configured. An external
+        /// input event is configured by the caller, who has to restore it.
+        pub fn is_stale(&self) -> bool {
+            !self.external && self.generation != WAKE_MASK_GENERATION.get()
+        }
+
+        /// Leaves the configuration in place without undoing it, but stops counting it as an armed wait so that the
+        /// overlap policy doesn't reject every later wait on this thread.
+        pub fn leak(self) {
+            if !self.external {
+                CONFIGURED.set(CONFIGURED.get() - 1);
+            }
+
+            std::mem::forget(self);
         }
     }
 
     impl Drop for ConfiguredInputEvent {
         fn drop(&mut self) {
+            if self.external {
+                return;
+            }
+
             // The order of the calls matches MsgWaitForMultipleObjectsEx.
             unsafe {
+                if self.queue_attach {
+                    // Otherwise, the next wait on this thread would be woken up by an attach it didn't ask for.
+                    _ = NtUserSetWaitForQueueAttach(false.into());
+                }
+
                 NtUserClearWakeMask().unwrap();
-                _ = NtUserReassociateQueueEventCompletionPacket();
+
+                if self.manage_completion_packet {
+                    _ = NtUserReassociateQueueEventCompletionPacket();
+                }
+            }
+
+            CONFIGURED.set(CONFIGURED.get() - 1);
+        }
+    }
+}
+
+mod wait_object {
+    use std::{
+        cell::RefCell,
+        ptr::null_mut,
+        rc::Rc,
+        sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use windows::{
+        Win32::System::Threading::{
+            CreateThreadpoolWait, PTP_CALLBACK_INSTANCE, PTP_WAIT, PTP_WAIT_CALLBACK,
+            SetThreadpoolWait, WaitForThreadpoolWaitCallbacks,
+        },
+        core::Owned,
+    };
+
+    use super::{InputEventFutureShared, record_leaked_wait};
+    use crate::Syscall;
+
+    thread_local! {
+        static CACHED_WAIT: RefCell<Option<Rc<CachedWait>>> = const { RefCell::new(None) };
+    }
+
+    /// The threadpool wait backing a single arm of an input event future.
+    #[derive(Default)]
+    pub enum WaitObject {
+        #[default]
+        None,
+        /// A wait created for this future alone.
+        Owned(Owned<PTP_WAIT>),
+        /// The thread's cached wait, claimed by this future until dropped.
+        Cached(CachedWaitClaim),
+    }
+
+    impl WaitObject {
+        /// Creates a wait that calls `callback` with `shared`.
+        ///
+        /// The thread's cached wait is only created once creation fails, so that it's around for
+        /// [`WaitObject::claim_cached`] should the caller's retries fail as well.
+        pub fn create(
+            callback: PTP_WAIT_CALLBACK,
+            shared: *mut InputEventFutureShared,
+        ) -> windows::core::Result<Self> {
+            match unsafe { CreateThreadpoolWait(callback, Some(shared as _), None) } {
+                Ok(wait) => Ok(Self::Owned(unsafe { Owned::new(wait) })),
+                Err(error) => {
+                    CachedWait::prepare();
+                    Err(Syscall::CreateThreadpoolWait.error(error))
+                }
+            }
+        }
+
+        /// Claims the thread's cached wait for `shared`, provided it has been created and no other future on this
+        /// thread is currently using it. Releasing the claim waits for a running callback for at most
+        /// `callback_timeout`.
+        pub fn claim_cached(
+            shared: *mut InputEventFutureShared,
+            callback_timeout: Duration,
+        ) -> Option<Self> {
+            CachedWait::claim(shared, callback_timeout).map(Self::Cached)
+        }
+
+        pub fn is_set(&self) -> bool {
+            !matches!(self, Self::None)
+        }
+
+        pub fn as_raw(&self) -> PTP_WAIT {
+            match self {
+                Self::None => PTP_WAIT::default(),
+                Self::Owned(wait) => **wait,
+                Self::Cached(claim) => *claim.cached_wait.ptp_wait,
+            }
+        }
+    }
+
+    /// A threadpool wait that is created once per thread and reused by whichever future currently claims it.
+    ///
+    /// The wait's context is a slot holding the claiming future's shared state, so it can outlive any single future.
+    pub struct CachedWait {
+        ptp_wait: Owned<PTP_WAIT>,
+        context: Box<CachedWaitContext>,
+    }
+
+    struct CachedWaitContext {
+        slot: AtomicPtr<InputEventFutureShared>,
+        /// The number of callbacks that may currently be accessing the claiming future's shared state.
+        active_callbacks: AtomicUsize,
+    }
+
+    impl CachedWait {
+        /// Creates the thread's cached wait if it doesn't exist yet.
+        fn prepare() {
+            CACHED_WAIT.with_borrow_mut(|cached_wait| {
+                if cached_wait.is_none() {
+                    *cached_wait = Self::new().ok().map(Rc::new);
+                }
+            });
+        }
+
+        fn claim(
+            shared: *mut InputEventFutureShared,
+            callback_timeout: Duration,
+        ) -> Option<CachedWaitClaim> {
+            let cached_wait = CACHED_WAIT.with_borrow(Option::clone)?;
+
+            cached_wait
+                .context
+                .slot
+                .compare_exchange(null_mut(), shared, Ordering::AcqRel, Ordering::Acquire)
+                .ok()?;
+
+            Some(CachedWaitClaim {
+                cached_wait,
+                callback_timeout,
+            })
+        }
+
+        fn new() -> windows::core::Result<Self> {
+            let context = Box::new(CachedWaitContext {
+                slot: AtomicPtr::new(null_mut()),
+                active_callbacks: AtomicUsize::new(0),
+            });
+            let ptp_wait = unsafe {
+                Owned::new(CreateThreadpoolWait(
+                    Some(Self::callback),
+                    Some(&raw const *context as _),
+                    None,
+                )?)
+            };
+
+            Ok(Self { ptp_wait, context })
+        }
+
+        unsafe extern "system" fn callback(
+            _instance: PTP_CALLBACK_INSTANCE,
+            context: *mut core::ffi::c_void,
+            _wait: PTP_WAIT,
+            _waitresult: u32,
+        ) {
+            let context = unsafe { &*(context as *const CachedWaitContext) };
+
+            context.active_callbacks.fetch_add(1, Ordering::AcqRel);
+            if let Some(shared) = unsafe { context.slot.load(Ordering::Acquire).as_ref() } {
+                shared.wait_completed();
+            }
+            context.active_callbacks.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    impl Drop for CachedWait {
+        fn drop(&mut self) {
+            // Only runs when the thread exits, and no claim is left by then, so any callback finds the slot empty and
+            // returns right away.
+            unsafe {
+                SetThreadpoolWait(*self.ptp_wait, None, None);
+                WaitForThreadpoolWaitCallbacks(*self.ptp_wait, true);
+            }
+        }
+    }
+
+    /// Exclusive use of the thread's cached wait. Releasing it waits for callbacks that may still access the claiming
+    /// future's shared state, for at most the callback timeout.
+    pub struct CachedWaitClaim {
+        cached_wait: Rc<CachedWait>,
+        callback_timeout: Duration,
+    }
+
+    impl Drop for CachedWaitClaim {
+        fn drop(&mut self) {
+            let context = &self.cached_wait.context;
+
+            unsafe { SetThreadpoolWait(*self.cached_wait.ptp_wait, None, None) };
+
+            // Callbacks that start from here on find the slot empty, so only the ones already running need to finish.
+            context.slot.store(null_mut(), Ordering::Release);
+
+            let deadline = Instant::now() + self.callback_timeout;
+            while context.active_callbacks.load(Ordering::Acquire) != 0 {
+                if Instant::now() >= deadline {
+                    // The stuck callback still references the context, so the cached wait is leaked and replaced by a
+                    // new one when the next wait is created.
+                    CACHED_WAIT.with_borrow_mut(|cached_wait| {
+                        if cached_wait
+                            .as_ref()
+                            .is_some_and(|cached_wait| Rc::ptr_eq(cached_wait, &self.cached_wait))
+                        {
+                            *cached_wait = None;
+                        }
+                    });
+
+                    std::mem::forget(Rc::clone(&self.cached_wait));
+                    record_leaked_wait();
+                    return;
+                }
+
+                std::thread::yield_now();
+            }
+        }
+    }
+}
+
+mod detached {
+    use std::{
+        cell::RefCell,
+        future::Future,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll, Wake, Waker},
+    };
+
+    use super::{InputEventFuture, MessageIterator};
+
+    type Notification = Box<dyn FnMut() + Send>;
+    type Drain = Box<dyn FnOnce(MessageIterator<'static>)>;
+
+    thread_local! {
+        static DETACHED: RefCell<Vec<Detached>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct Detached {
+        future: InputEventFuture,
+        /// Notifies the arming thread, and is reused whenever a filtered wait is armed again.
+        waker: Waker,
+        drain: Option<Drain>,
+    }
+
+    impl Detached {
+        /// Resolves the future if it's ready, handing the messages to the drain. Returns the wait if it's pending.
+        fn resolve(mut self) -> Option<Self> {
+            let waker = self.waker.clone();
+
+            match Pin::new(&mut self.future).poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(Ok(messages)) => {
+                    if let Some(drain) = self.drain {
+                        drain(messages);
+                    }
+
+                    None
+                }
+                Poll::Ready(Err(_)) => None,
+                Poll::Pending => Some(self),
+            }
+        }
+    }
+
+    /// Calls the notification on every wake.
+    struct NotifyWaker(Mutex<Notification>);
+
+    impl Wake for NotifyWaker {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            (self.0.lock().unwrap())();
+        }
+    }
+
+    pub fn detach(future: InputEventFuture, on_ready: Notification, drain: Option<Drain>) {
+        let waker = Waker::from(Arc::new(NotifyWaker(Mutex::new(on_ready))));
+        let detached = Detached {
+            future,
+            waker: waker.clone(),
+            drain,
+        };
+
+        match detached.resolve() {
+            Some(pending) => DETACHED.with_borrow_mut(|detached| detached.push(pending)),
+            None => waker.wake(),
+        }
+    }
+
+    /// Releases detached waits that have completed and drains their messages. Has to be called on the thread that
+    /// armed them.
+    pub fn reap() {
+        // Completed futures are moved out first so that their destructors and drains don't run while the list is
+        // borrowed.
+        let completed = DETACHED.with_borrow_mut(|detached| {
+            let (completed, pending) = std::mem::take(detached)
+                .into_iter()
+                .partition::<Vec<_>, _>(|detached| detached.future.is_ready());
+            *detached = pending;
+            completed
+        });
+
+        for detached in completed {
+            // A filtered wait that was woken up by other messages is armed again, and notifies once more when it
+            // completes.
+            if let Some(pending) = detached.resolve() {
+                DETACHED.with_borrow_mut(|detached| detached.push(pending));
             }
         }
     }
@@ -99,10 +719,33 @@ struct InputEventFutureShared {
     state: AtomicU32,
     waker_in_use: AtomicBool,
     waker: Option<Waker>,
+    wake_hook: Option<WakeHook>,
+    callback_done: AtomicBool,
 }
 
 impl InputEventFutureShared {
-    pub fn wait_done(&self) {
+    fn with_wake_hook(wake_hook: Option<WakeHook>) -> Self {
+        Self {
+            wake_hook,
+            ..Self::default()
+        }
+    }
+
+    /// Called from the threadpool callback once the input event has been signaled.
+    pub fn wait_completed(&self) {
+        let completed = self.wait_done();
+
+        if let Some(wake_hook) = self.wake_hook.as_ref().filter(|_| completed) {
+            let mut wake_hook = wake_hook.lock().unwrap();
+            (*wake_hook)();
+        }
+
+        // The future waits for this before releasing the shared state, so this has to be the last access.
+        self.callback_done.store(true, Ordering::Release);
+    }
+
+    /// Marks the wait as done and wakes the waker. Returns `false` if the future has been cancelled.
+    fn wait_done(&self) -> bool {
         let old_state = self
             .state
             .swap(InputEventFutureState::Ready as _, Ordering::AcqRel);
@@ -110,7 +753,7 @@ impl InputEventFutureShared {
         // If old_state is NotPending, there is nothing to wake as poll() will immediately return Ready.
         // If old_state is Cancelled, the future is being dropped and there is no need to wake the waker
         if old_state != InputEventFutureState::Pending as u32 {
-            return;
+            return old_state != InputEventFutureState::Cancelled as u32;
         }
 
         while self
@@ -120,6 +763,8 @@ impl InputEventFutureShared {
         {}
         self.waker.as_ref().unwrap().wake_by_ref();
         self.waker_in_use.store(false, Ordering::Release);
+
+        true
     }
 }
 
@@ -129,37 +774,468 @@ impl Default for InputEventFutureShared {
             state: AtomicU32::new(InputEventFutureState::NotPending as _),
             waker_in_use: AtomicBool::new(false),
             waker: None,
+            wake_hook: None,
+            callback_done: AtomicBool::new(false),
         }
     }
 }
 
-struct InputEventFuture {
+/// Waits for messages to arrive in the calling thread's message queue.
+///
+/// The wait is only armed once the future is polled. Dropping the future while the wait is armed cancels it, which
+/// blocks until a concurrently running threadpool callback has finished. Use [`InputEventFuture::detach`] to let the
+/// wait complete on its own instead.
+///
+/// The state shared with the threadpool callback lives in a separate heap allocation, whose address is what the
+/// callback receives as its context. The future itself is therefore `Unpin` and can be moved freely, even while a wait
+/// is pending, without the callback losing track of it.
+///
+/// # `WM_PAINT`
+///
+/// Unlike other messages, `WM_PAINT` is never queued. The system generates it on demand for as long as a window's
+/// update region is invalid, and `QS_PAINT` stays set until the region is validated by `BeginPaint`, `ValidateRect` or
+/// `DefWindowProcW`. Retrieving a `WM_PAINT` without validating it therefore doesn't empty the queue, and a wait for
+/// `QS_PAINT` would resolve over and over again for the same paint.
+///
+/// To avoid that hot loop, the future treats a `QS_PAINT` as handled if it's the only thing left in the queue and a
+/// [`MessageIterator`] has already yielded its `WM_PAINT`, i.e. after a batch retrieved it without validating the
+/// region. It then arms the wait without `QS_PAINT` and checks the queue again every [`PAINT_RECHECK_INTERVAL`], so a
+/// window that is validated and invalidated anew is reported again after at most that interval. Dispatching the
+/// `WM_PAINT` to a window procedure that validates the region, as `DefWindowProcW` does, avoids the delay. A paint
+/// queued behind messages that a batch stopped short of is still reported right away, as it hasn't been yielded yet.
+#[must_use = "the wait does nothing unless awaited"]
+pub struct InputEventFuture {
     queue_status_flags: u16,
     wait_flags: u16,
+    options: WaitOptions,
+    last_queue_status: Cell<Option<u32>>,
+    // Whether the last check found nothing but a `WM_PAINT` that has already been reported, see the type docs.
+    paint_deferred: Cell<bool>,
+    // Wakes the future to recheck a deferred paint, or to retry creating the threadpool wait.
+    recheck_timer: Option<RecheckTimer>,
+    create_wait_attempts: u32,
+    // Whether the filter has rejected a wake. Messages outside the range then stay queued, so the wait is armed
+    // without `MWMO_INPUTAVAILABLE` from then on, as it would keep waking up for them.
+    filter_missed: bool,
+    spin_deadline: Option<Instant>,
     input_event: Option<ConfiguredInputEvent>,
-    shared: InputEventFutureShared,
-    ptp_wait: Owned<PTP_WAIT>,
-    _marker: PhantomPinned,
+    // Lives in its own allocation, so that its address stays stable when the future moves and it can be leaked if the
+    // callback never finishes.
+    shared: ManuallyDrop<Box<InputEventFutureShared>>,
+    ptp_wait: WaitObject,
 }
 
 impl InputEventFuture {
-    pub fn new(queue_status_flags: u16, wait_flags: u16) -> Self {
+    pub(crate) fn new(queue_status_flags: u16, wait_flags: u16, options: WaitOptions) -> Self {
         Self {
             queue_status_flags,
             wait_flags,
+            options,
+            last_queue_status: Cell::new(None),
+            paint_deferred: Cell::new(false),
+            recheck_timer: None,
+            create_wait_attempts: 0,
+            filter_missed: false,
+            spin_deadline: None,
             input_event: None,
-            shared: InputEventFutureShared::default(),
-            ptp_wait: Owned::default(),
-            _marker: PhantomPinned,
+            shared: ManuallyDrop::new(Box::default()),
+            ptp_wait: WaitObject::default(),
         }
     }
 
+    /// Creates a future that waits on an input event which the caller has already configured, rather than
+    /// configuring the thread's input event itself.
+    ///
+    /// This is meant for composing with other code that waits for messages and sets up the input event on its own. The
+    /// future never calls `NtUserGetInputEvent`, never touches the wake mask or the queue's wait completion packet, and
+    /// ignores `MWMO_QUEUEATTACH`; it only waits for `input_event` to be signaled, and uses the flags to check whether
+    /// messages are already queued. Returns `E_INVALIDARG` if `input_event` is null or the flags are invalid.
+    ///
+    /// # Safety
+    ///
+    /// `input_event` must be the input event of the thread that polls the future, as returned by
+    /// `NtUserGetInputEvent`, and must stay configured with a wake mask matching `queue_status_flags` and `wait_flags`
+    /// for as long as the future lives. On Windows 10 and later, the queue's wait completion packet must stay cancelled
+    /// for that time as well, see [`MessageWaiterBuilder::manage_completion_packet`]. The handle must stay valid until
+    /// the future is dropped.
+    ///
+    /// [`MessageWaiterBuilder::manage_completion_packet`]: crate::MessageWaiterBuilder::manage_completion_packet
+    pub unsafe fn from_raw_parts(
+        input_event: HANDLE,
+        queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+        wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    ) -> windows::core::Result<Self> {
+        let (queue_status_flags, wait_flags) =
+            narrow_flags(queue_status_flags.into(), wait_flags.into())?;
+
+        let mut options = WaitOptions::default();
+        options.set_external_input_event(input_event)?;
+
+        Ok(Self::new(queue_status_flags, wait_flags, options))
+    }
+
+    /// Arms the wait and lets it run to completion without being torn down when dropped.
+    ///
+    /// `on_ready` is called once messages are available, either right away on the calling thread or later on a
+    /// threadpool thread. It must not touch the message queue itself, as the messages belong to the calling thread;
+    /// use it to notify that thread instead, e.g. by sending into a channel.
+    ///
+    /// A wait with a [message filter](InputEventFuture::with_message_filter) can be woken up by messages outside the
+    /// range, which only the calling thread can tell apart. `on_ready` is therefore called for every wake: reaping
+    /// such a wait arms it again, and `on_ready` is called once more when it completes.
+    ///
+    /// The threadpool can't release the wait's thread-bound resources, so a completed wait is only released on the
+    /// calling thread: by [`reap_detached`], the next time a wait is armed on that thread, or when the thread exits.
+    /// Until then, the thread's wake mask stays set, the queue's wait completion packet stays cancelled, and the wait
+    /// still counts as armed for [`OverlapPolicy::Error`] and [`OverlapPolicy::Panic`]. Call [`reap_detached`] once
+    /// `on_ready` has notified the thread.
+    pub fn detach(self, on_ready: impl FnMut() + Send + 'static) {
+        detached::detach(self, Box::new(on_ready), None);
+    }
+
+    /// Like [`InputEventFuture::detach`], but also drains the messages into `on_messages` once the completed wait is
+    /// released on the calling thread, e.g. to forward them into a channel.
+    ///
+    /// `on_messages` runs on the calling thread, either right away if messages are already queued, or from
+    /// [`reap_detached`] or the next poll of a wait on that thread. Messages it doesn't consume stay queued.
+    pub fn detach_draining(
+        self,
+        on_ready: impl FnMut() + Send + 'static,
+        on_messages: impl FnOnce(MessageIterator<'static>) + 'static,
+    ) {
+        detached::detach(self, Box::new(on_ready), Some(Box::new(on_messages)));
+    }
+
+    /// Registers a hook that is called on the threadpool thread right after the wait completes and the waker has been
+    /// woken up.
+    ///
+    /// This is meant for low-latency signaling, e.g. releasing a semaphore or bumping a counter.
+    ///
+    /// # Safety
+    ///
+    /// The hook runs on a threadpool thread, not on the thread that armed the wait. It must not block, must not
+    /// access the message queue of the waiting thread, and must not call into this crate.
+    pub unsafe fn with_wake_hook(self, wake_hook: impl FnMut() + Send + 'static) -> Self {
+        self.with_shared_wake_hook(Some(Arc::new(Mutex::new(wake_hook))))
+    }
+
+    pub(crate) fn with_shared_wake_hook(mut self, wake_hook: Option<WakeHook>) -> Self {
+        self.shared.wake_hook = wake_hook;
+        self
+    }
+
+    /// Restricts the wait and the returned iterator to messages within `range`.
+    ///
+    /// The wake mask can only express message categories, not ranges, so the wait may wake up for messages outside of
+    /// the range. In that case, the wait is re-armed instead of resolving, and the out-of-range messages are left in
+    /// the queue. As they would otherwise keep waking it up, the wait is re-armed without `MWMO_INPUTAVAILABLE`.
+    pub fn with_message_filter(mut self, range: RangeInclusive<u32>) -> Self {
+        self.options.message_filter.set_range(range);
+        self
+    }
+
+    /// Restricts the wait and the returned iterator to the message categories selected by the `PM_QS_*` qualifiers,
+    /// e.g. `PM_QS_POSTMESSAGE` to leave input and paint messages queued.
+    ///
+    /// Returns `E_INVALIDARG` if `qualifiers` contains anything but `PM_QS_*` flags.
+    pub fn with_peek_qualifiers(
+        mut self,
+        qualifiers: PEEK_MESSAGE_REMOVE_TYPE,
+    ) -> windows::core::Result<Self> {
+        self.options.message_filter.set_qualifiers(qualifiers)?;
+        Ok(self)
+    }
+
+    /// Describes the current state of the wait, e.g. for diagnosing a future that never wakes up.
+    pub fn explain_wait_state(&self) -> WaitStateSnapshot {
+        let state = match self.shared.state.load(Ordering::Acquire) {
+            state if state == InputEventFutureState::Pending as u32 => WaitState::Pending,
+            state if state == InputEventFutureState::Ready as u32 => WaitState::Ready,
+            state if state == InputEventFutureState::Cancelled as u32 => WaitState::Cancelled,
+            _ => WaitState::NotPending,
+        };
+
+        WaitStateSnapshot {
+            state,
+            queue_status_flags: QUEUE_STATUS_FLAGS(self.queue_status_flags.into()),
+            wait_flags: MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS(self.wait_flags.into()),
+            wait_armed: self.ptp_wait.is_set(),
+            // The waker is only replaced on this thread, so reading it here doesn't race with the callback.
+            waker_registered: self.shared.waker.is_some(),
+            last_queue_status: self.last_queue_status.get(),
+        }
+    }
+
+    /// Sets how long dropping the future waits for a running threadpool callback, see
+    /// [`MessageWaiterBuilder::callback_timeout`](crate::MessageWaiterBuilder::callback_timeout).
+    pub fn with_callback_timeout(mut self, timeout: Duration) -> Self {
+        self.options.callback_timeout = timeout;
+        self
+    }
+
+    /// Sets whether the queue's wait completion packet is cancelled while waiting, see
+    /// [`MessageWaiterBuilder::manage_completion_packet`](crate::MessageWaiterBuilder::manage_completion_packet).
+    pub fn with_completion_packet_management(mut self, manage: bool) -> Self {
+        self.options.manage_completion_packet = manage;
+        self
+    }
+
+    /// Sets whether the queue is drained as soon as the wait resolves, see
+    /// [`MessageWaiterBuilder::eager_drain`](crate::MessageWaiterBuilder::eager_drain).
+    pub fn with_eager_drain(mut self, eager: bool) -> Self {
+        self.options.eager_drain = eager;
+        self
+    }
+
+    /// Sets what polling does if another wait is already armed on this thread, see [`OverlapPolicy`].
+    pub fn with_overlap_policy(mut self, policy: OverlapPolicy) -> Self {
+        self.options.overlap_policy = policy;
+        self
+    }
+
+    /// Sets whether the returned iterator reports [`MouseMoveStats`], see
+    /// [`MessageWaiterBuilder::track_mouse_moves`](crate::MessageWaiterBuilder::track_mouse_moves).
+    pub fn with_mouse_move_tracking(mut self, track: bool) -> Self {
+        self.options.track_mouse_moves = track;
+        self
+    }
+
+    /// Sets how the wait is armed, see [`Backend`].
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.options.backend = backend;
+        self
+    }
+
+    /// Sets how long polling busy-checks the queue before arming the wait, see
+    /// [`MessageWaiterBuilder::spin_budget`](crate::MessageWaiterBuilder::spin_budget).
+    pub fn with_spin_budget(mut self, budget: Option<Duration>) -> Self {
+        self.options.spin_budget = budget;
+        self
+    }
+
+    /// Returns whether the spin budget hasn't been used up yet, starting it on the first call.
+    fn spinning(self: Pin<&mut Self>) -> bool {
+        let Some(budget) = self.options.spin_budget else {
+            return false;
+        };
+
+        let this = self.get_mut();
+        let deadline = *this
+            .spin_deadline
+            .get_or_insert_with(|| Instant::now() + budget);
+        Instant::now() < deadline
+    }
+
+    fn is_ready(&self) -> bool {
+        self.shared.state.load(Ordering::Acquire) == InputEventFutureState::Ready as u32
+    }
+
     fn ready(self: Pin<&mut Self>) -> Poll<<Self as Future>::Output> {
-        let this = unsafe { self.get_unchecked_mut() };
-        std::mem::drop(this.input_event.take());
-        std::mem::drop(std::mem::take(&mut this.ptp_wait));
+        let options = self.options;
+        self.disarm();
+
+        Poll::Ready(options.messages())
+    }
+
+    /// Polls a wait using [`Backend::Reactor`], which never touches the shared state or a threadpool wait.
+    fn poll_reactor(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<<Self as Future>::Output> {
+        // Re-arm from scratch on every poll, so that the wake mask is only set while the queue is known to be empty.
+        reactor::unregister(self.reactor_owner());
+        self.input_event = None;
+
+        // Configuring the input event would replace the wake mask of the wait that has reserved the reactor.
+        reactor::check_available(self.reactor_owner())?;
+
+        if self.messages_available()? {
+            return Poll::Ready(self.options.messages());
+        }
+
+        self.schedule_paint_recheck(cx)?;
+        let input_event = self.configure_input_event()?;
+
+        reactor::register(
+            self.reactor_owner(),
+            input_event.handle().as_raw(),
+            cx.waker(),
+        )?;
+        self.input_event = Some(input_event);
+
+        Poll::Pending
+    }
+
+    /// Identifies this wait's registration with the reactor. The shared state is boxed, so its address is stable and
+    /// unique while the future is alive.
+    fn reactor_owner(&self) -> usize {
+        (&raw const **self.shared).addr()
+    }
+
+    /// Configures the thread's input event for this wait, or wraps the external one, which is already configured.
+    fn configure_input_event(&self) -> windows::core::Result<ConfiguredInputEvent> {
+        let queue_status_flags = if self.paint_deferred.get() {
+            self.queue_status_flags & !(QS_PAINT.0 as u16)
+        } else {
+            self.queue_status_flags
+        };
+
+        let wait_flags = if self.filter_missed {
+            self.wait_flags & !(MWMO_INPUTAVAILABLE.0 as u16)
+        } else {
+            self.wait_flags
+        };
 
-        Poll::Ready(Ok(MessageIterator::default()))
+        match self.options.external_input_event {
+            Some(input_event) => Ok(ConfiguredInputEvent::external(input_event)),
+            None => ConfiguredInputEvent::new(
+                queue_status_flags,
+                wait_flags,
+                self.options.manage_completion_packet,
+                self.options.overlap_policy,
+            ),
+        }
+    }
+
+    /// Polls the future again after [`PAINT_RECHECK_INTERVAL`] if the wait is about to be armed without `QS_PAINT`.
+    fn schedule_paint_recheck(&mut self, cx: &Context) -> windows::core::Result<()> {
+        if !self.paint_deferred.get() {
+            return Ok(());
+        }
+
+        if self.recheck_timer.is_none() {
+            self.recheck_timer = Some(RecheckTimer::new()?);
+        }
+
+        self.recheck_timer
+            .as_ref()
+            .unwrap()
+            .schedule(PAINT_RECHECK_INTERVAL, cx.waker());
+        Ok(())
+    }
+
+    /// Creates the threadpool wait, or schedules another attempt with a short backoff if creation fails and retries
+    /// are left. Once they're exhausted, the thread's cached wait is claimed instead, if it's available.
+    ///
+    /// The backoff runs on the recheck timer rather than sleeping, so that the executor thread isn't blocked.
+    fn create_wait(&mut self, cx: &Context) -> Poll<windows::core::Result<WaitObject>> {
+        let error = match WaitObject::create(Some(Self::callback), &raw mut **self.shared) {
+            Ok(wait) => {
+                self.create_wait_attempts = 0;
+                return Poll::Ready(Ok(wait));
+            }
+            Err(error) => error,
+        };
+
+        if self.create_wait_attempts >= self.options.create_wait_retries {
+            self.create_wait_attempts = 0;
+            return Poll::Ready(
+                WaitObject::claim_cached(&raw mut **self.shared, self.options.callback_timeout)
+                    .ok_or(error),
+            );
+        }
+
+        let backoff = Duration::from_millis(1u64 << self.create_wait_attempts.min(6));
+        self.create_wait_attempts += 1;
+
+        if self.recheck_timer.is_none() {
+            self.recheck_timer = RecheckTimer::new().ok();
+        }
+
+        match &self.recheck_timer {
+            Some(recheck_timer) => recheck_timer.schedule(backoff, cx.waker()),
+            // The timer can't be created under the same resource exhaustion, so retry on the next poll right away.
+            None => cx.waker().wake_by_ref(),
+        }
+
+        Poll::Pending
+    }
+
+    /// Releases the input event and the threadpool wait of a completed wait.
+    fn disarm(self: Pin<&mut Self>) {
+        let this = self.get_mut();
+
+        if !this.release() {
+            // The leaked shared state is still in use by the callback, the next wait needs a fresh one.
+            let wake_hook = this.shared.wake_hook.clone();
+            this.shared =
+                ManuallyDrop::new(Box::new(InputEventFutureShared::with_wake_hook(wake_hook)));
+        }
+    }
+
+    /// Cancels the wait if it's still pending and releases the input event and the threadpool wait.
+    ///
+    /// If the callback is running, this waits for it to finish for at most the configured callback timeout. Should it
+    /// take longer, everything it may still access is leaked rather than blocking indefinitely, and `false` is
+    /// returned; the caller must not free the shared state in that case.
+    fn release(&mut self) -> bool {
+        if self.ptp_wait.is_set() {
+            let cancelled = self
+                .shared
+                .state
+                .compare_exchange(
+                    InputEventFutureState::Pending as _,
+                    InputEventFutureState::Cancelled as _,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+                && unsafe { SetThreadpoolWaitEx(self.ptp_wait.as_raw(), None, None, None) }
+                    .as_bool();
+
+            if !cancelled && !self.wait_for_callback() {
+                record_leaked_wait();
+
+                std::mem::forget(std::mem::take(&mut self.ptp_wait));
+                if let Some(input_event) = self.input_event.take() {
+                    input_event.leak();
+                }
+                return false;
+            }
+        }
+
+        std::mem::drop(std::mem::take(&mut self.ptp_wait));
+        std::mem::drop(self.input_event.take());
+        true
+    }
+
+    /// Waits for the callback of a wait that couldn't be cancelled to finish, up to the callback timeout.
+    fn wait_for_callback(&self) -> bool {
+        let start = Instant::now();
+        let deadline = start + self.options.callback_timeout;
+
+        while !self.shared.callback_done.load(Ordering::Acquire) {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+
+            // Sleeping only pays off once the callback is stuck in a wake hook or waker.
+            if now - start < CALLBACK_YIELD_DURATION {
+                std::thread::yield_now();
+            } else {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        // The callback has stopped touching the shared state, now wait for it to actually return.
+        unsafe { WaitForThreadpoolWaitCallbacks(self.ptp_wait.as_raw(), false) };
+        true
+    }
+
+    /// Checks whether messages that the future resolves for are queued.
+    fn messages_available(&self) -> windows::core::Result<bool> {
+        // Sent messages are never retrieved, so only the queue status can tell whether there are any.
+        if self.options.message_filter.is_unfiltered() || self.options.sent_only {
+            let queue_status = queue_status(self.queue_status_flags, self.wait_flags)?;
+            self.last_queue_status.set(Some(queue_status));
+
+            let paint_deferred =
+                only_reported_paint(QueueStatus(queue_status), self.queue_status_flags);
+            self.paint_deferred.set(paint_deferred);
+
+            Ok(queue_status > 0 && !paint_deferred)
+        } else {
+            self.paint_deferred.set(false);
+            Ok(self.options.message_filter.has_message())
+        }
     }
 
     unsafe extern "system" fn callback(
@@ -169,38 +1245,55 @@ impl InputEventFuture {
         _waitresult: u32,
     ) {
         let this = unsafe { &*(context as *const InputEventFutureShared) };
-        this.wait_done();
+        this.wait_completed();
     }
 }
 
 impl Drop for InputEventFuture {
     fn drop(&mut self) {
-        if self
-            .shared
-            .state
-            .compare_exchange(
-                InputEventFutureState::Pending as _,
-                InputEventFutureState::Cancelled as _,
-                Ordering::AcqRel,
-                Ordering::Acquire,
-            )
-            .is_ok()
-        {
-            unsafe {
-                if !SetThreadpoolWaitEx(*self.ptp_wait, None, None, None).as_bool() {
-                    WaitForThreadpoolWaitCallbacks(*self.ptp_wait, true);
-                }
-            }
+        if self.options.backend == Backend::Reactor {
+            reactor::unregister(self.reactor_owner());
+        }
+
+        if self.release() {
+            unsafe { ManuallyDrop::drop(&mut self.shared) };
         }
     }
 }
 
 impl Future for InputEventFuture {
-    type Output = windows::core::Result<MessageIterator>;
+    type Output = windows::core::Result<MessageIterator<'static>>;
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         let state = self.shared.state.load(Ordering::Acquire);
         if state == InputEventFutureState::Ready as u32 {
-            return self.ready();
+            if self.options.message_filter.is_unfiltered()
+                || self.options.sent_only
+                || self.options.message_filter.has_message()
+                || self.options.deadline_passed()
+            {
+                return self.ready();
+            }
+
+            // Woken up by messages outside of the filter range, which stay queued - wait again.
+            self.as_mut().disarm();
+            self.filter_missed = true;
+            self.shared
+                .state
+                .store(InputEventFutureState::NotPending as _, Ordering::Release);
+        } else if state == InputEventFutureState::Pending as u32
+            && (self.paint_deferred.get()
+                || self
+                    .input_event
+                    .as_ref()
+                    .is_some_and(ConfiguredInputEvent::is_stale))
+        {
+            // Woken up by the paint recheck, or by something else while the wait is armed without QS_PAINT, or the wake
+            // mask has been replaced underneath the wait. Check whether the update region has been validated or other
+            // messages arrived, and arm the wait again.
+            self.as_mut().disarm();
+            self.shared
+                .state
+                .store(InputEventFutureState::NotPending as _, Ordering::Release);
         } else if state == InputEventFutureState::Pending as u32 {
             match self.shared.waker_in_use.compare_exchange(
                 false,
@@ -209,11 +1302,8 @@ impl Future for InputEventFuture {
                 Ordering::Acquire,
             ) {
                 Ok(_) => {
-                    unsafe {
-                        let this = self.get_unchecked_mut();
-                        this.shared.waker = Some(cx.waker().clone());
-                        this.shared.waker_in_use.store(false, Ordering::Release);
-                    }
+                    self.shared.waker = Some(cx.waker().clone());
+                    self.shared.waker_in_use.store(false, Ordering::Release);
                     return Poll::Pending;
                 }
                 Err(_) => {
@@ -223,54 +1313,61 @@ impl Future for InputEventFuture {
             }
         }
 
-        let queue_status = unsafe {
-            NtUserGetQueueStatusReadonly(make_dword(self.queue_status_flags, self.wait_flags))
-        }?;
+        if self.options.spin_budget.is_some() {
+            if self.messages_available()? {
+                return Poll::Ready(self.options.messages());
+            }
 
-        // Messages are already in the queue
-        if queue_status > 0 {
-            return Poll::Ready(Ok(MessageIterator::default()));
+            // Yield to the executor and check again on the next poll, rather than arming the wait.
+            if self.as_mut().spinning() {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
         }
 
-        let wait = unsafe {
-            Owned::new(CreateThreadpoolWait(
-                Some(Self::callback),
-                Some({
-                    let this = self.as_mut().get_unchecked_mut();
-                    &raw mut this.shared as _
-                }),
-                None,
-            )?)
-        };
-        unsafe {
-            let this = self.as_mut().get_unchecked_mut();
-
-            this.input_event = Some(ConfiguredInputEvent::new(
-                this.queue_status_flags,
-                this.wait_flags,
-            )?);
+        if self.options.backend == Backend::Reactor {
+            return self.poll_reactor(cx);
         }
 
-        if self.queue_status_flags & (MWMO_QUEUEATTACH.0 as u16) != 0 {
-            unsafe {
-                _ = NtUserSetWaitForQueueAttach(true.into())?;
-            }
+        // Messages are already in the queue
+        if self.messages_available()? {
+            return Poll::Ready(self.options.messages());
         }
 
-        unsafe {
-            let this = self.as_mut().get_unchecked_mut();
-            this.shared.waker = Some(cx.waker().clone());
-            this.ptp_wait = wait;
+        // The deadline passed without messages, resolve with an empty batch.
+        if self.options.deadline_passed() {
+            return Poll::Ready(self.options.messages());
         }
 
+        self.schedule_paint_recheck(cx)?;
+
+        let wait = ready!(self.create_wait(cx))?;
+
+        detached::reap();
+
+        self.input_event = Some(self.configure_input_event()?);
+
+        self.shared.waker = Some(cx.waker().clone());
+        self.shared.callback_done.store(false, Ordering::Release);
+        self.ptp_wait = wait;
+
+        // Re-arming after a spurious wake-up only waits for what is left until the deadline.
+        let timeout = self
+            .options
+            .deadline
+            .map(|deadline| relative_due_time(deadline.saturating_duration_since(Instant::now())));
+
         unsafe {
             SetThreadpoolWait(
-                *self.ptp_wait,
-                Some(self.input_event.as_ref().unwrap().as_raw()),
-                None,
+                self.ptp_wait.as_raw(),
+                Some(self.input_event.as_ref().unwrap().handle().as_raw()),
+                timeout.as_ref().map(|timeout| timeout as *const _),
             );
         }
 
+        // The input event may already be signaled, so the callback can run before the state is set to Pending. This
+        // can't lose the wake-up: the callback unconditionally swaps the state to Ready, so either it sees Pending and
+        // wakes the waker set above, or it sees NotPending and this exchange fails.
         match self.shared.state.compare_exchange(
             InputEventFutureState::NotPending as _,
             InputEventFutureState::Pending as _,
@@ -286,41 +1383,320 @@ impl Future for InputEventFuture {
     }
 }
 
-pub fn wait_for_messages(
+/// Releases the detached waits on the calling thread that have completed, and drains their messages if they were
+/// detached with [`InputEventFuture::detach_draining`].
+///
+/// See [`InputEventFuture::detach`] for why completed waits have to be released on the thread that armed them.
+pub fn reap_detached() {
+    detached::reap();
+}
+
+/// Reads the queue status for the given flags without clearing the "new messages" state.
+pub(crate) fn queue_status(queue_status_flags: u16, wait_flags: u16) -> windows::core::Result<u32> {
+    unsafe { NtUserGetQueueStatusReadonly(make_dword(queue_status_flags, wait_flags)) }
+        .map_err(|error| Syscall::NtUserGetQueueStatus.error(error))
+}
+
+thread_local! {
+    /// Whether a [`MessageIterator`] has retrieved the thread's pending `WM_PAINT` since it was last reported as added.
+    static PAINT_RETRIEVED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Records that a [`MessageIterator`] draining the thread's queue has yielded a `WM_PAINT`.
+pub(crate) fn record_paint_retrieved() {
+    PAINT_RETRIEVED.set(true);
+}
+
+/// Returns whether `status`, read for `queue_status_flags`, holds nothing but a `WM_PAINT` that an iterator has
+/// already yielded without the update region being validated since, see [`InputEventFuture`].
+///
+/// Retrieving any message clears the "added" bit of `QS_PAINT`, so the bit alone can't tell whether the paint has been
+/// retrieved, e.g. if only a posted message in front of it was.
+fn only_reported_paint(status: QueueStatus, queue_status_flags: u16) -> bool {
+    if queue_status_flags & QS_PAINT.0 as u16 == 0 {
+        return false;
+    }
+
+    // A validated paint is gone, and a newly added one has to be yielded before it counts as retrieved.
+    if status.current().0 & QS_PAINT.0 == 0 || status.added().0 & QS_PAINT.0 != 0 {
+        PAINT_RETRIEVED.set(false);
+    }
+
+    status.current() == QS_PAINT && PAINT_RETRIEVED.get()
+}
+
+/// Validates the flags and narrows them to the representation used by the input event.
+///
+/// Both kinds of flags share a single DWORD, so neither may use the upper 16 bits. Flags that
+/// don't fit are rejected rather than truncated, as are queue status flags outside of
+/// [`SUPPORTED_QUEUE_STATUS_FLAGS`].
+pub(crate) fn narrow_flags(
     queue_status_flags: QUEUE_STATUS_FLAGS,
     wait_flags: MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS,
-) -> windows::core::Result<impl Future<Output = windows::core::Result<impl Iterator<Item = MSG>>>> {
+) -> windows::core::Result<(u16, u16)> {
     if wait_flags.0 & (MWMO_ALERTABLE.0 | MWMO_WAITALL.0) != 0 {
-        return Err(E_INVALIDARG.into());
+        return Err(windows::core::Error::new(
+            E_INVALIDARG,
+            "MWMO_ALERTABLE and MWMO_WAITALL are not supported",
+        ));
     }
 
-    let queue_status_flags = queue_status_flags.0.try_into().map_err(|_| E_INVALIDARG)?;
-    let wait_flags = wait_flags.0.try_into().map_err(|_| E_INVALIDARG)?;
+    let queue_status_flags = queue_status_flags.0.try_into().map_err(|_| {
+        windows::core::Error::new(E_INVALIDARG, "queue status flags don't fit into 16 bits")
+    })?;
+    let wait_flags = wait_flags.0.try_into().map_err(|_| {
+        windows::core::Error::new(E_INVALIDARG, "wait flags don't fit into 16 bits")
+    })?;
 
-    Ok(InputEventFuture::new(queue_status_flags, wait_flags))
+    if u32::from(queue_status_flags) & !SUPPORTED_QUEUE_STATUS_FLAGS.0 != 0 {
+        return Err(windows::core::Error::new(
+            E_INVALIDARG,
+            "queue status flags contain reserved bits, such as QS_EVENT",
+        ));
+    }
+
+    Ok((queue_status_flags, wait_flags))
 }
 
-struct MessageIterator {
-    _marker: PhantomData<*mut ()>,
+pub fn wait_for_messages(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+) -> windows::core::Result<InputEventFuture> {
+    let (queue_status_flags, wait_flags) =
+        narrow_flags(queue_status_flags.into(), wait_flags.into())?;
+
+    Ok(InputEventFuture::new(
+        queue_status_flags,
+        wait_flags,
+        WaitOptions::default(),
+    ))
 }
 
-impl Default for MessageIterator {
-    fn default() -> Self {
+/// The outcome of [`try_or_wait`].
+#[must_use]
+pub enum TryOrWait {
+    /// Messages were already queued and can be drained right away.
+    Ready(MessageIterator<'static>),
+    /// The queue was empty. The future hasn't been polled yet, so no wait has been armed.
+    Wait(InputEventFuture),
+}
+
+/// Returns the queued messages if there are any, or a future waiting for new ones otherwise.
+///
+/// This is the fast path of [`InputEventFuture`] exposed to the caller: if the queue status is non-zero, the
+/// messages are returned without creating a threadpool wait or touching the input event, unless the only thing queued is
+/// a `WM_PAINT` that has already been retrieved, see [`InputEventFuture`]. Use [`wait_for_messages`] if you always want
+/// a future.
+pub fn try_or_wait(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+) -> windows::core::Result<TryOrWait> {
+    let (queue_status_flags, wait_flags) =
+        narrow_flags(queue_status_flags.into(), wait_flags.into())?;
+
+    let status = QueueStatus(queue_status(queue_status_flags, wait_flags)?);
+    if !status.is_empty() && !only_reported_paint(status, queue_status_flags) {
+        Ok(TryOrWait::Ready(MessageIterator::default()))
+    } else {
+        Ok(TryOrWait::Wait(InputEventFuture::new(
+            queue_status_flags,
+            wait_flags,
+            WaitOptions::default(),
+        )))
+    }
+}
+
+/// Drains the calling thread's message queue, one `PeekMessageW` per item.
+///
+/// By default, messages are removed lazily while iterating, so the iterator reflects the queue at the time of
+/// iterating: messages posted after the wake are included, and messages that someone else removed in the meantime are
+/// not. With [`MessageWaiterBuilder::eager_drain`](crate::MessageWaiterBuilder::eager_drain), the queue is drained as
+/// soon as the wait resolves instead, and the iterator yields exactly the messages that were queued at that time. With
+/// [`MessageWaiterBuilder::sent_messages_only`](crate::MessageWaiterBuilder::sent_messages_only), the iterator only
+/// processes sent messages and yields nothing.
+///
+/// The iterator is bound to the thread it was created on. When obtained from a [`crate::MessageWaiter`], it borrows
+/// the waiter and has to be dropped before the waiter can be polled again.
+pub struct MessageIterator<'a, S = ThreadQueue> {
+    source: S,
+    drain: Drain<'a>,
+    mouse_move_reported: Option<bool>,
+    mouse_moves_drained: u64,
+    _marker: PhantomData<(&'a (), *mut ())>,
+}
+
+enum Drain<'a> {
+    Lazy(MessageFilter),
+    Eager(std::vec::IntoIter<MSG>),
+    /// Drained eagerly into a [`crate::MessageWaiter`]'s buffer.
+    Buffered(std::vec::Drain<'a, MSG>),
+    Sent,
+}
+
+impl MessageIterator<'_> {
+    pub(crate) fn with_filter(filter: MessageFilter) -> Self {
+        Self::new(ThreadQueue, Drain::Lazy(filter))
+    }
+
+    /// Processes sent messages only, see [`process_sent_messages`](crate::process_sent_messages).
+    pub(crate) fn sent_only() -> Self {
+        Self::new(ThreadQueue, Drain::Sent)
+    }
+
+    /// Drains all messages matching `filter` right away.
+    pub(crate) fn drain_now(filter: MessageFilter) -> Self {
+        let mut lazy = Self::with_filter(filter);
+        let messages = lazy.by_ref().collect::<Vec<_>>();
+
+        let mut eager = Self::new(ThreadQueue, Drain::Eager(messages.into_iter()));
+        eager.mouse_moves_drained = lazy.mouse_moves_drained;
+        eager
+    }
+}
+
+impl<'a> MessageIterator<'a> {
+    /// Drains all remaining messages into `buffer` right away, replacing its contents, and yields them from there.
+    ///
+    /// Unlike [`MessageIterator::drain_now`], this doesn't allocate once the buffer has grown large enough.
+    pub(crate) fn drain_now_into(mut self, buffer: &'a mut Vec<MSG>) -> Self {
+        if let Drain::Lazy(_) = self.drain {
+            buffer.clear();
+            buffer.extend(self.by_ref());
+            self.drain = Drain::Buffered(buffer.drain(..));
+        }
+
+        self
+    }
+}
+
+impl<'a, S: MessageSource> MessageIterator<'a, S> {
+    fn new(source: S, drain: Drain<'a>) -> Self {
         MessageIterator {
+            source,
+            drain,
+            mouse_move_reported: None,
+            mouse_moves_drained: 0,
             _marker: PhantomData,
         }
     }
+
+    /// Drains every message from `source` rather than from the thread's queue, e.g. a
+    /// [`ScriptedMessages`](crate::ScriptedMessages) in tests.
+    pub fn from_source(source: S) -> Self {
+        Self::new(source, Drain::Lazy(MessageFilter::default()))
+    }
+
+    /// Drains the messages within `range` from `source`, leaving the others in it.
+    pub fn from_source_filtered(source: S, range: RangeInclusive<u32>) -> Self {
+        let mut filter = MessageFilter::default();
+        filter.set_range(range);
+
+        Self::new(source, Drain::Lazy(filter))
+    }
+
+    /// Removes all remaining messages and appends them to `buffer`, returning how many were appended.
+    ///
+    /// Unlike collecting the iterator, this appends every message straight to the buffer, so a buffer that is reused
+    /// across batches doesn't allocate once it has grown large enough. If retrieving a message fails, the messages
+    /// retrieved so far stay in the buffer and the error is returned.
+    pub fn drain_into(&mut self, buffer: &mut Vec<MSG>) -> windows::core::Result<usize> {
+        let filter = match &mut self.drain {
+            Drain::Lazy(filter) => *filter,
+            Drain::Eager(messages) => {
+                let len = messages.len();
+                buffer.extend(messages);
+                return Ok(len);
+            }
+            Drain::Buffered(messages) => {
+                let len = messages.len();
+                buffer.extend(messages);
+                return Ok(len);
+            }
+            Drain::Sent => {
+                process_sent_messages();
+                return Ok(0);
+            }
+        };
+
+        let start = buffer.len();
+
+        while let Some(msg) =
+            self.source
+                .peek_message(filter.min, filter.max, PM_REMOVE | filter.qualifiers)?
+        {
+            self.mouse_moves_drained += u64::from(msg.message == WM_MOUSEMOVE);
+            buffer.push(msg);
+        }
+
+        Ok(buffer.len() - start)
+    }
+
+    /// Returns how often the queue reported a new mouse move for this batch compared to the `WM_MOUSEMOVE` messages
+    /// drained from it so far, or `None` unless the batch comes from a wait that
+    /// [tracks mouse moves](crate::MessageWaiterBuilder::track_mouse_moves).
+    pub fn mouse_move_stats(&self) -> Option<MouseMoveStats> {
+        self.mouse_move_reported.map(|reported| MouseMoveStats {
+            reported: reported.into(),
+            drained: self.mouse_moves_drained,
+        })
+    }
+
+    /// Tracks mouse moves as if the queue had reported a new mouse move, or not, when the batch's wait resolved, so
+    /// that [`MessageIterator::mouse_move_stats`] can be tested with scripted messages.
+    #[cfg(feature = "test-util")]
+    pub fn with_reported_mouse_move(mut self, reported: bool) -> Self {
+        self.mouse_move_reported = Some(reported);
+        self
+    }
+
+    /// Yields [`Message`](crate::Message)s instead of raw [`MSG`]s.
+    pub fn typed(self) -> TypedMessageIterator<'a, S> {
+        TypedMessageIterator::new(self)
+    }
+
+    /// Lends the messages in chunks of up to `chunk_size`, drained into `buffer`, see [`LendingMessages`].
+    ///
+    /// Pass the same buffer for every batch to reuse its allocation.
+    ///
+    /// [`LendingMessages`]: crate::LendingMessages
+    #[cfg(feature = "lending")]
+    pub fn lend_into(
+        self,
+        buffer: &'a mut Vec<MSG>,
+        chunk_size: std::num::NonZeroUsize,
+    ) -> crate::LendingMessages<'a, S> {
+        crate::LendingMessages::new(self, buffer, chunk_size)
+    }
 }
 
-impl Iterator for MessageIterator {
+impl Default for MessageIterator<'_> {
+    fn default() -> Self {
+        Self::with_filter(MessageFilter::default())
+    }
+}
+
+impl<S: MessageSource> Iterator for MessageIterator<'_, S> {
     type Item = MSG;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut msg = MaybeUninit::uninit();
-        if unsafe { PeekMessageW(msg.as_mut_ptr(), None, 0, 0, PM_REMOVE).as_bool() } {
-            Some(unsafe { msg.assume_init() })
-        } else {
-            None
-        }
+        let filter = match &mut self.drain {
+            Drain::Lazy(filter) => filter,
+            Drain::Eager(messages) => return messages.next(),
+            Drain::Buffered(messages) => return messages.next(),
+            Drain::Sent => {
+                process_sent_messages();
+                return None;
+            }
+        };
+
+        // Failing to retrieve a message ends the iteration, like an empty queue.
+        let msg = self
+            .source
+            .peek_message(filter.min, filter.max, PM_REMOVE | filter.qualifiers)
+            .ok()
+            .flatten()?;
+        self.mouse_moves_drained += u64::from(msg.message == WM_MOUSEMOVE);
+
+        Some(msg)
     }
 }
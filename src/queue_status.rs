@@ -0,0 +1,251 @@
+use std::{
+    ffi::c_void,
+    future::poll_fn,
+    ops::AddAssign,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll, Waker, ready},
+    time::Duration,
+};
+
+use windows::{
+    Win32::{
+        Foundation::FILETIME,
+        System::Threading::{
+            CreateThreadpoolTimer, PTP_CALLBACK_INSTANCE, PTP_TIMER, SetThreadpoolTimer,
+            WaitForThreadpoolTimerCallbacks,
+        },
+        UI::WindowsAndMessaging::{MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, QUEUE_STATUS_FLAGS},
+    },
+    core::Owned,
+};
+
+use crate::{
+    Syscall,
+    bindings::has_readonly_queue_status,
+    msg_future::{InputEventFuture, WaitOptions, narrow_flags, queue_status},
+};
+
+/// How often a [`QueueStatusStream`] checks a queue that still holds the messages it has already reported.
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_millis(16);
+
+/// A reading of the queue status, as returned by `GetQueueStatus`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct QueueStatus(pub u32);
+
+impl QueueStatus {
+    /// The kinds of messages currently in the queue.
+    pub fn current(self) -> QUEUE_STATUS_FLAGS {
+        QUEUE_STATUS_FLAGS(self.0 >> 16)
+    }
+
+    /// The kinds of messages added since the thread last retrieved messages or cleared the status.
+    pub fn added(self) -> QUEUE_STATUS_FLAGS {
+        QUEUE_STATUS_FLAGS(self.0 & 0xFFFF)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// How the mouse moves reported by the queue status compare to the `WM_MOUSEMOVE` messages actually drained, see
+/// [`MessageWaiterBuilder::track_mouse_moves`](crate::MessageWaiterBuilder::track_mouse_moves).
+///
+/// The system doesn't queue a message per mouse move; it merges moves into the single `WM_MOUSEMOVE` at the end of the
+/// queue. The counts can't tell how many physical moves were merged, but a flood of moves that outpaces draining
+/// shows up as batches for which a new move was reported without any `WM_MOUSEMOVE` being drained. Stats of several
+/// batches can be summed up with `+=`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MouseMoveStats {
+    /// The number of wakes at which the queue status reported `QS_MOUSEMOVE` as newly added.
+    pub reported: u64,
+    /// The number of `WM_MOUSEMOVE` messages drained.
+    pub drained: u64,
+}
+
+impl MouseMoveStats {
+    /// The number of reported mouse moves that didn't result in a drained `WM_MOUSEMOVE`.
+    pub fn coalesced(self) -> u64 {
+        self.reported.saturating_sub(self.drained)
+    }
+}
+
+impl AddAssign for MouseMoveStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.reported += rhs.reported;
+        self.drained += rhs.drained;
+    }
+}
+
+/// The system call used to read the queue status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QueueStatusBackend {
+    /// `NtUserGetQueueStatusReadonly`, which reads the queue status without side effects.
+    Readonly,
+    /// `NtUserGetQueueStatus`, the system call behind `GetQueueStatus`, used where the readonly variant doesn't exist.
+    ///
+    /// Besides returning the status, it clears the low word, which tracks the kinds of messages added since the
+    /// status was last read, as if those messages had been seen. A wait without `MWMO_INPUTAVAILABLE` only completes
+    /// for messages added after that point, so messages that arrived between checking the queue and arming the wait
+    /// can go unnoticed until the next message arrives. Use `MWMO_INPUTAVAILABLE` or
+    /// [`MessageWaiterBuilder::require_readonly_queue_status`](crate::MessageWaiterBuilder::require_readonly_queue_status)
+    /// if that matters.
+    Mutating,
+}
+
+/// Returns the system call used to read the queue status on this system.
+pub fn queue_status_backend() -> QueueStatusBackend {
+    if has_readonly_queue_status() {
+        QueueStatusBackend::Readonly
+    } else {
+        QueueStatusBackend::Mutating
+    }
+}
+
+/// Converts `delay` into the due time of a threadpool timer or wait, which is relative if negative, in 100 ns units.
+pub(crate) fn relative_due_time(delay: Duration) -> FILETIME {
+    let due_time = -((delay.as_nanos() / 100).min(i64::MAX as u128) as i64);
+    FILETIME {
+        dwLowDateTime: due_time as u32,
+        dwHighDateTime: (due_time >> 32) as u32,
+    }
+}
+
+/// Wakes a waker once after a delay.
+pub(crate) struct RecheckTimer {
+    timer: Owned<PTP_TIMER>,
+    waker: Box<Mutex<Option<Waker>>>,
+}
+
+impl RecheckTimer {
+    pub fn new() -> windows::core::Result<Self> {
+        let waker = Box::new(Mutex::new(None));
+        let timer = unsafe {
+            Owned::new(
+                CreateThreadpoolTimer(Some(Self::callback), Some(&raw const *waker as _), None)
+                    .map_err(|error| Syscall::CreateThreadpoolTimer.error(error))?,
+            )
+        };
+
+        Ok(Self { timer, waker })
+    }
+
+    pub fn schedule(&self, delay: Duration, waker: &Waker) {
+        self.waker.lock().unwrap().replace(waker.clone());
+        let due_time = relative_due_time(delay);
+
+        unsafe {
+            SetThreadpoolTimer(*self.timer, Some(&raw const due_time), 0, 0);
+        }
+    }
+
+    unsafe extern "system" fn callback(
+        _instance: PTP_CALLBACK_INSTANCE,
+        context: *mut c_void,
+        _timer: PTP_TIMER,
+    ) {
+        let waker = unsafe { &*(context as *const Mutex<Option<Waker>>) };
+        if let Some(waker) = waker.lock().unwrap().take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for RecheckTimer {
+    fn drop(&mut self) {
+        // The callback only wakes the waker, so waiting for it can't block for long.
+        unsafe {
+            SetThreadpoolTimer(*self.timer, None, 0, 0);
+            WaitForThreadpoolTimerCallbacks(*self.timer, true);
+        }
+    }
+}
+
+/// Reports changes of the queue status without removing any messages.
+///
+/// Every item is the queue status read right after the wait was woken up. Messages stay queued for whoever pumps
+/// them, which usually is another task on the same thread.
+///
+/// As long as the queue still holds messages, waiting on it would complete immediately. To avoid spinning, the stream
+/// only arms a wait while the queue is empty. Otherwise, it rechecks the queue status at most once per minimum
+/// interval and only reports a status that differs from the last one; this means that a queue that is drained and
+/// refilled with the same kinds of messages between two checks is not reported again.
+pub struct QueueStatusStream {
+    queue_status_flags: u16,
+    wait_flags: u16,
+    min_interval: Duration,
+    future: Option<InputEventFuture>,
+    timer: RecheckTimer,
+    last: Option<QueueStatus>,
+}
+
+impl QueueStatusStream {
+    /// Sets how often the queue status is rechecked while the queue isn't empty. Defaults to [`DEFAULT_MIN_INTERVAL`].
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Polls for the next change of the queue status.
+    pub fn poll_next(&mut self, cx: &mut Context) -> Poll<windows::core::Result<QueueStatus>> {
+        loop {
+            let Some(last) = self.last else {
+                let (queue_status_flags, wait_flags) = (self.queue_status_flags, self.wait_flags);
+                let future = self.future.get_or_insert_with(|| {
+                    InputEventFuture::new(queue_status_flags, wait_flags, WaitOptions::default())
+                });
+
+                // Dropping the iterator leaves the messages queued.
+                ready!(Pin::new(future).poll(cx))?;
+                self.future = None;
+
+                let status = QueueStatus(queue_status(self.queue_status_flags, self.wait_flags)?);
+                if status.is_empty() {
+                    // Woken up by sent messages that have been processed in the meantime.
+                    continue;
+                }
+
+                self.last = Some(status);
+                return Poll::Ready(Ok(status));
+            };
+
+            let status = QueueStatus(queue_status(self.queue_status_flags, self.wait_flags)?);
+            if status.is_empty() {
+                self.last = None;
+                continue;
+            }
+
+            if status != last {
+                self.last = Some(status);
+                return Poll::Ready(Ok(status));
+            }
+
+            self.timer.schedule(self.min_interval, cx.waker());
+            return Poll::Pending;
+        }
+    }
+
+    /// Waits for the next change of the queue status.
+    pub async fn next(&mut self) -> windows::core::Result<QueueStatus> {
+        poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+/// Creates a [`QueueStatusStream`] for the given flags.
+pub fn queue_status_stream(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+) -> windows::core::Result<QueueStatusStream> {
+    let (queue_status_flags, wait_flags) =
+        narrow_flags(queue_status_flags.into(), wait_flags.into())?;
+
+    Ok(QueueStatusStream {
+        queue_status_flags,
+        wait_flags,
+        min_interval: DEFAULT_MIN_INTERVAL,
+        future: None,
+        timer: RecheckTimer::new()?,
+        last: None,
+    })
+}
@@ -0,0 +1,156 @@
+use std::{
+    cell::{Cell, RefCell},
+    future::Future,
+    task::Waker,
+};
+
+use windows::Win32::{
+    Foundation::{E_ILLEGAL_METHOD_CALL, HANDLE, WAIT_FAILED, WAIT_OBJECT_0},
+    System::Threading::{INFINITE, WaitForMultipleObjects, WaitForSingleObject},
+};
+
+use crate::executor::block_on_event;
+
+/// How a wait for messages is armed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// Waits on a threadpool thread, which works with any executor.
+    #[default]
+    Threadpool,
+    /// Lets the executor wait on the thread's input event directly, avoiding the threadpool wait and the cross-thread
+    /// wake-up entirely. Only works within [`block_on_reactor`]; polling fails with `E_ILLEGAL_METHOD_CALL` elsewhere.
+    ///
+    /// Only one wait using this backend can be pending per thread at a time, as every wait would share the thread's
+    /// input event and its single wake mask. Polling another one while a wait is pending, e.g. when joining two
+    /// futures, fails with `E_ILLEGAL_METHOD_CALL` as well.
+    Reactor,
+}
+
+struct Registration {
+    /// Identifies the wait that registered, so that no other wait can replace or remove the registration.
+    owner: usize,
+    input_event: HANDLE,
+    waker: Waker,
+    /// Whether the input event has been signaled since the wait registered. The registration is kept to reserve the
+    /// reactor for the wait until it's polled again, but isn't waited for anymore.
+    woken: bool,
+}
+
+thread_local! {
+    static RUNNING: Cell<bool> = const { Cell::new(false) };
+    static REGISTRATION: RefCell<Option<Registration>> = const { RefCell::new(None) };
+}
+
+/// Checks whether the wait identified by `owner` may register, i.e. whether the reactor is running and not reserved by
+/// another wait.
+pub(crate) fn check_available(owner: usize) -> windows::core::Result<()> {
+    if !RUNNING.get() {
+        return Err(windows::core::Error::new(
+            E_ILLEGAL_METHOD_CALL,
+            "the reactor backend can only be used within block_on_reactor",
+        ));
+    }
+
+    REGISTRATION.with_borrow(|registration| match registration {
+        Some(registration) if registration.owner != owner => Err(windows::core::Error::new(
+            E_ILLEGAL_METHOD_CALL,
+            "another wait using the reactor backend is already pending on this thread",
+        )),
+        _ => Ok(()),
+    })
+}
+
+/// Registers `waker` to be woken once `input_event` is signaled, replacing the previous registration of `owner`.
+pub(crate) fn register(
+    owner: usize,
+    input_event: HANDLE,
+    waker: &Waker,
+) -> windows::core::Result<()> {
+    check_available(owner)?;
+
+    REGISTRATION.replace(Some(Registration {
+        owner,
+        input_event,
+        waker: waker.clone(),
+        woken: false,
+    }));
+
+    Ok(())
+}
+
+/// Removes the registration of `owner`, if any.
+pub(crate) fn unregister(owner: usize) {
+    REGISTRATION.with_borrow_mut(|registration| {
+        if registration
+            .as_ref()
+            .is_some_and(|registration| registration.owner == owner)
+        {
+            *registration = None;
+        }
+    });
+}
+
+struct RunningGuard;
+
+impl RunningGuard {
+    fn enter() -> windows::core::Result<Self> {
+        if RUNNING.replace(true) {
+            return Err(windows::core::Error::new(
+                E_ILLEGAL_METHOD_CALL,
+                "block_on_reactor is already running on this thread",
+            ));
+        }
+
+        Ok(Self)
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.set(false);
+        REGISTRATION.take();
+    }
+}
+
+/// Runs `future` to completion on the current thread, waiting for messages on behalf of [`Backend::Reactor`] waits.
+///
+/// Between polls, this blocks until either the future is woken or the thread's input event is signaled for a wait
+/// using the reactor backend. Waits using the threadpool backend work as well.
+pub fn block_on_reactor<F: Future>(future: F) -> windows::core::Result<F::Output> {
+    let _guard = RunningGuard::enter()?;
+
+    block_on_event(future, |event| {
+        let input_event = REGISTRATION.with_borrow(|registration| {
+            registration
+                .as_ref()
+                .filter(|registration| !registration.woken)
+                .map(|registration| registration.input_event)
+        });
+
+        let Some(input_event) = input_event else {
+            return match unsafe { WaitForSingleObject(event, INFINITE) } {
+                WAIT_FAILED => Err(windows::core::Error::from_win32()),
+                _ => Ok(()),
+            };
+        };
+
+        match unsafe { WaitForMultipleObjects(&[event, input_event], false, INFINITE) } {
+            WAIT_FAILED => Err(windows::core::Error::from_win32()),
+            result if result.0 == WAIT_OBJECT_0.0 + 1 => {
+                let waker = REGISTRATION.with_borrow_mut(|registration| {
+                    registration.as_mut().map(|registration| {
+                        registration.woken = true;
+                        registration.waker.clone()
+                    })
+                });
+
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+                Ok(())
+            }
+            // Woken up for something else; the wait is still armed.
+            _ => Ok(()),
+        }
+    })
+}
@@ -0,0 +1,141 @@
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker, ready},
+};
+
+use windows::Win32::UI::WindowsAndMessaging::{
+    MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, QUEUE_STATUS_FLAGS,
+};
+
+use crate::{
+    QueueStatus,
+    msg_future::{InputEventFuture, WaitOptions, narrow_flags, queue_status},
+};
+
+/// Wakes every subscriber that polled since the last wake.
+#[derive(Default)]
+struct FanOutWaker(Mutex<Vec<Waker>>);
+
+impl FanOutWaker {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.0.lock().unwrap();
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+impl Wake for FanOutWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let wakers = std::mem::take(&mut *self.0.lock().unwrap());
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+struct SignalState {
+    queue_status_flags: u16,
+    wait_flags: u16,
+    future: Option<InputEventFuture>,
+    generation: u64,
+    status: QueueStatus,
+    fan_out: Arc<FanOutWaker>,
+}
+
+impl SignalState {
+    fn poll_generation(
+        &mut self,
+        generation: u64,
+        cx: &mut Context,
+    ) -> Poll<windows::core::Result<QueueStatus>> {
+        if self.generation >= generation {
+            return Poll::Ready(Ok(self.status));
+        }
+
+        self.fan_out.register(cx.waker());
+
+        let (queue_status_flags, wait_flags) = (self.queue_status_flags, self.wait_flags);
+        let future = self.future.get_or_insert_with(|| {
+            InputEventFuture::new(queue_status_flags, wait_flags, WaitOptions::default())
+        });
+
+        let waker = Waker::from(self.fan_out.clone());
+        let result = ready!(Pin::new(future).poll(&mut Context::from_waker(&waker)));
+        self.future = None;
+
+        // The other subscribers find out about the new generation when they poll again.
+        self.fan_out.wake_by_ref();
+
+        // Discarding the iterator leaves the messages queued for the consumer.
+        result?;
+        let status = QueueStatus(queue_status(self.queue_status_flags, self.wait_flags)?);
+        self.generation += 1;
+        self.status = status;
+
+        Poll::Ready(Ok(status))
+    }
+}
+
+/// Shares a single wait for messages between any number of tasks on the same thread.
+///
+/// Only one wait can be armed per thread, so tasks that merely want to know when messages arrived should
+/// [`subscribe`](MessageSignal::subscribe) instead of arming their own. The signal never drains the queue: subscribers
+/// receive the queue status at the time of the wake, and removing the messages remains the job of a single designated
+/// consumer. As long as messages are still queued, new subscriptions resolve right away.
+pub struct MessageSignal {
+    state: Rc<RefCell<SignalState>>,
+}
+
+impl MessageSignal {
+    pub fn new(
+        queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+        wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    ) -> windows::core::Result<Self> {
+        let (queue_status_flags, wait_flags) =
+            narrow_flags(queue_status_flags.into(), wait_flags.into())?;
+
+        Ok(Self {
+            state: Rc::new(RefCell::new(SignalState {
+                queue_status_flags,
+                wait_flags,
+                future: None,
+                generation: 0,
+                status: QueueStatus::default(),
+                fan_out: Arc::default(),
+            })),
+        })
+    }
+
+    /// Returns a future that resolves to the queue status once the next wake after this call happens.
+    ///
+    /// The future can be cloned; every clone resolves for the same wake.
+    pub fn subscribe(&self) -> Subscription {
+        Subscription {
+            state: self.state.clone(),
+            generation: self.state.borrow().generation + 1,
+        }
+    }
+}
+
+/// A subscription to the next wake of a [`MessageSignal`].
+#[derive(Clone)]
+#[must_use = "the subscription does nothing unless awaited"]
+pub struct Subscription {
+    state: Rc<RefCell<SignalState>>,
+    generation: u64,
+}
+
+impl Future for Subscription {
+    type Output = windows::core::Result<QueueStatus>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.state.borrow_mut().poll_generation(self.generation, cx)
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    future::{Future, poll_fn},
+    marker::PhantomData,
+    pin::pin,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, unbounded_channel},
+    task::JoinHandle,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    MSG, MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, QUEUE_STATUS_FLAGS, WM_QUIT,
+};
+
+use crate::{MessageWaiter, QuitCode};
+
+/// Receives the messages forwarded by [`spawn_message_source`].
+///
+/// Messages belong to the thread that drained them, so the receiver has to stay on that thread.
+pub struct MessageReceiver {
+    receiver: UnboundedReceiver<MSG>,
+    _marker: PhantomData<*mut ()>,
+}
+
+impl MessageReceiver {
+    pub async fn recv(&mut self) -> Option<MSG> {
+        self.receiver.recv().await
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<MSG>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Spawns a task on the current `LocalSet` that forwards every message from the thread's queue into a channel.
+///
+/// The task stops and resolves to the exit code once `WM_QUIT` is received, which is not forwarded. If the receiver
+/// is dropped first, the pending wait is cancelled and the task resolves to `None`.
+///
+/// # Panics
+///
+/// Panics if called outside of a `LocalSet`, like [`tokio::task::spawn_local`].
+pub fn spawn_message_source(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+) -> windows::core::Result<(
+    JoinHandle<windows::core::Result<Option<QuitCode>>>,
+    MessageReceiver,
+)> {
+    let mut waiter = MessageWaiter::new(queue_status_flags, wait_flags)?;
+    let (sender, receiver) = unbounded_channel();
+
+    let handle = tokio::task::spawn_local(async move {
+        let mut closed = pin!(sender.closed());
+
+        loop {
+            let batch = {
+                let mut wait = pin!(waiter.wait());
+
+                poll_fn(|cx| {
+                    if closed.as_mut().poll(cx).is_ready() {
+                        return Poll::Ready(None);
+                    }
+
+                    wait.as_mut().poll(cx).map(Some)
+                })
+                .await
+            };
+
+            // The receiver has been dropped, dropping the waiter cancels the wait.
+            let Some(batch) = batch else {
+                return Ok(None);
+            };
+            let mut batch = batch?;
+
+            // Checking the receiver before removing each message leaves the rest queued once it's gone.
+            while !sender.is_closed() {
+                let Some(msg) = batch.next() else {
+                    break;
+                };
+
+                if msg.message == WM_QUIT {
+                    return Ok(Some(msg.wParam.into()));
+                }
+
+                if sender.send(msg).is_err() {
+                    return Ok(None);
+                }
+            }
+        }
+    });
+
+    Ok((
+        handle,
+        MessageReceiver {
+            receiver,
+            _marker: PhantomData,
+        },
+    ))
+}
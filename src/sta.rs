@@ -0,0 +1,67 @@
+use std::future::Future;
+
+use windows::Win32::{
+    Foundation::{HANDLE, RPC_E_WRONG_THREAD, RPC_S_CALLPENDING, WAIT_FAILED},
+    System::{
+        Com::{
+            APTTYPE_MAINSTA, APTTYPE_STA, COWAIT_DISPATCH_CALLS, CoGetApartmentType,
+            CoWaitForMultipleHandles,
+        },
+        Threading::INFINITE,
+    },
+    UI::WindowsAndMessaging::{MWMO_NONE, MsgWaitForMultipleObjectsEx, QS_ALLINPUT},
+};
+
+use crate::{executor::block_on_event, msg_future::invalidate_wake_masks};
+
+/// Runs `future` to completion on the current single-threaded apartment, servicing COM calls while it's idle.
+///
+/// A thread in an STA has to keep pumping, otherwise incoming cross-apartment calls can't be delivered and callers
+/// deadlock. The futures of this crate wait on a threadpool thread instead, so an executor that simply blocks
+/// between polls would never pump. This executor lets `CoWaitForMultipleHandles` enter COM's modal loop instead,
+/// which dispatches incoming calls.
+///
+/// In an STA, the modal loop only dispatches the few window messages COM relies on; all other messages stay queued
+/// for [`crate::wait_for_messages`] and friends.
+///
+/// The executor itself blocks in `MsgWaitForMultipleObjectsEx` on all kinds of messages, and only hands over to the
+/// modal loop for a single pass once messages arrive. Incoming calls are delivered as messages, so they're dispatched
+/// by that pass, while a wait for e.g. `QS_KEY` is polled again with its own mask once input arrives, even if no COM
+/// call is in flight.
+///
+/// Both wait functions configure the thread's wake mask for what they wait for and clear it again before returning.
+/// As there is only one wake mask per thread, the futures of this crate can't keep their own while the executor is
+/// blocked. Whenever the executor wakes up, pending waits are armed again with their own mask when they are polled
+/// next, so messages that arrived in the meantime are picked up.
+///
+/// Returns `RPC_E_WRONG_THREAD` if the calling thread hasn't been initialized as an STA.
+pub fn block_on_sta<F: Future>(future: F) -> windows::core::Result<F::Output> {
+    let mut apartment_type = Default::default();
+    let mut apartment_qualifier = Default::default();
+    unsafe { CoGetApartmentType(&mut apartment_type, &mut apartment_qualifier)? };
+
+    if apartment_type != APTTYPE_STA && apartment_type != APTTYPE_MAINSTA {
+        return Err(RPC_E_WRONG_THREAD.into());
+    }
+
+    block_on_event(future, |event| {
+        let result = wait_and_dispatch_calls(event);
+        invalidate_wake_masks();
+
+        result
+    })
+}
+
+/// Waits for `event` or any new message, then lets COM dispatch the incoming calls that arrived meanwhile.
+fn wait_and_dispatch_calls(event: HANDLE) -> windows::core::Result<()> {
+    if unsafe { MsgWaitForMultipleObjectsEx(Some(&[event]), INFINITE, QS_ALLINPUT, MWMO_NONE) }
+        == WAIT_FAILED
+    {
+        return Err(windows::core::Error::from_win32());
+    }
+
+    match unsafe { CoWaitForMultipleHandles(COWAIT_DISPATCH_CALLS.0 as _, 0, &[event]) } {
+        Err(error) if error.code() == RPC_S_CALLPENDING => Ok(()),
+        result => result.map(|_| ()),
+    }
+}
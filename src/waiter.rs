@@ -0,0 +1,511 @@
+use std::{
+    future::{Future, poll_fn},
+    ops::RangeInclusive,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, ready},
+    time::{Duration, Instant},
+};
+
+use windows::Win32::{
+    Foundation::{E_ILLEGAL_METHOD_CALL, E_INVALIDARG, ERROR_PROC_NOT_FOUND, HANDLE},
+    UI::WindowsAndMessaging::{
+        MSG, MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, MWMO_NONE, PEEK_MESSAGE_REMOVE_TYPE,
+        QS_HOTKEY, QS_SENDMESSAGE, QUEUE_STATUS_FLAGS, WM_HOTKEY,
+    },
+};
+
+use crate::{
+    Backend, QueueStatusBackend,
+    diagnostics::{WaitState, WaitStateSnapshot},
+    msg_future::{
+        InputEventFuture, MessageIterator, OverlapPolicy, WaitOptions, WakeHook, narrow_flags,
+    },
+    queue_status_backend,
+};
+
+/// What [`MessageWaiter::set_mask`] does if a wait is pending.
+///
+/// A pending wait has configured the thread's input event with the old mask, and the wait is only armed for message
+/// categories in that mask. Swapping the mask underneath it would make the wait resolve for, or check the queue for,
+/// categories it isn't armed for, so the pending wait always keeps the mask it was armed with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MaskChangePolicy {
+    /// Keep waiting with the old mask and apply the new one once the next wait is armed.
+    #[default]
+    Defer,
+    /// Fail with `E_ILLEGAL_METHOD_CALL` and keep the old mask.
+    Reject,
+}
+
+/// A reusable message waiter.
+///
+/// Unlike [`crate::wait_for_messages`], the waiter is kept alive across batches and can be polled by borrowing it,
+/// which makes it easy to embed in `select!` loops via [`std::future::poll_fn`].
+pub struct MessageWaiter {
+    queue_status_flags: u16,
+    wait_flags: u16,
+    options: WaitOptions,
+    wake_hook: Option<WakeHook>,
+    mask_change_policy: MaskChangePolicy,
+    future: Option<InputEventFuture>,
+    buffer: Vec<MSG>,
+}
+
+impl MessageWaiter {
+    pub fn new(
+        queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+        wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    ) -> windows::core::Result<Self> {
+        Self::builder(queue_status_flags, wait_flags).build()
+    }
+
+    pub fn builder(
+        queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+        wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    ) -> MessageWaiterBuilder {
+        MessageWaiterBuilder {
+            queue_status_flags: queue_status_flags.into(),
+            wait_flags: wait_flags.into(),
+            options: WaitOptions::default(),
+            wake_hook: None,
+            mask_change_policy: MaskChangePolicy::Defer,
+            buffer_capacity: 0,
+            peek_qualifiers: PEEK_MESSAGE_REMOVE_TYPE::default(),
+            require_readonly_queue_status: false,
+            input_event: None,
+        }
+    }
+
+    /// Polls for the next batch of messages, arming a wait if none is pending.
+    ///
+    /// The returned iterator borrows the waiter, so it has to be drained (or dropped) before the next poll.
+    /// Dropping the waiter while a wait is pending cancels the wait.
+    pub fn poll_next_batch(
+        &mut self,
+        cx: &mut Context,
+    ) -> Poll<windows::core::Result<MessageIterator<'_>>> {
+        let messages = ready!(self.poll_batch(cx))?;
+        Poll::Ready(Ok(self.drain_eagerly(messages)))
+    }
+
+    /// Polls the wait for the next batch, whose messages are removed lazily. Draining eagerly is up to the caller, who
+    /// can drain into the waiter's buffer rather than allocating a new one for every batch.
+    fn poll_batch(
+        &mut self,
+        cx: &mut Context,
+    ) -> Poll<windows::core::Result<MessageIterator<'static>>> {
+        let (queue_status_flags, wait_flags, options) = (
+            self.queue_status_flags,
+            self.wait_flags,
+            WaitOptions {
+                eager_drain: false,
+                ..self.options
+            },
+        );
+        let wake_hook = &self.wake_hook;
+        let future = self.future.get_or_insert_with(|| {
+            InputEventFuture::new(queue_status_flags, wait_flags, options)
+                .with_shared_wake_hook(wake_hook.clone())
+        });
+
+        let result = ready!(Pin::new(future).poll(cx));
+        self.future = None;
+
+        Poll::Ready(result)
+    }
+
+    /// Changes which messages the waiter waits for, e.g. to ignore input while a modal operation runs.
+    ///
+    /// The input event is bound to the mask it was configured with, so every wait configures it anew when it's armed.
+    /// Changing the mask between batches therefore takes effect with the next call to [`MessageWaiter::wait`] or
+    /// [`MessageWaiter::poll_next_batch`]. If a wait is still pending, e.g. because a `select!` dropped it before it
+    /// resolved, the [`MaskChangePolicy`] decides whether the change is deferred until that wait has resolved or
+    /// rejected.
+    ///
+    /// Fails with `E_INVALIDARG` for flags that [`MessageWaiter::new`] would reject, and with `E_ILLEGAL_METHOD_CALL` if
+    /// the waiter waits on an external input event, whose mask is configured by the caller.
+    pub fn set_mask(
+        &mut self,
+        queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+        wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    ) -> windows::core::Result<()> {
+        let queue_status_flags = queue_status_flags.into();
+        let (narrowed_queue_status_flags, wait_flags) =
+            narrow_flags(queue_status_flags, wait_flags.into())?;
+
+        if self.options.external_input_event.is_some() {
+            return Err(windows::core::Error::new(
+                E_ILLEGAL_METHOD_CALL,
+                "the mask of an external input event is configured by the caller",
+            ));
+        }
+
+        if self.options.sent_only && queue_status_flags != QS_SENDMESSAGE {
+            return Err(windows::core::Error::new(
+                E_INVALIDARG,
+                "processing sent messages only requires waiting for QS_SENDMESSAGE only",
+            ));
+        }
+
+        if self.future.is_some() && self.mask_change_policy == MaskChangePolicy::Reject {
+            return Err(windows::core::Error::new(
+                E_ILLEGAL_METHOD_CALL,
+                "a wait is pending with the current mask",
+            ));
+        }
+
+        // A pending wait has captured the old mask, the next one is created with the new mask.
+        self.queue_status_flags = narrowed_queue_status_flags;
+        self.wait_flags = wait_flags;
+        Ok(())
+    }
+
+    /// Describes the state of the pending wait, or of an idle waiter if no wait is pending.
+    pub fn explain_wait_state(&self) -> WaitStateSnapshot {
+        match &self.future {
+            Some(future) => future.explain_wait_state(),
+            None => WaitStateSnapshot {
+                state: WaitState::NotPending,
+                queue_status_flags: QUEUE_STATUS_FLAGS(self.queue_status_flags.into()),
+                wait_flags: MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS(self.wait_flags.into()),
+                wait_armed: false,
+                waker_registered: false,
+                last_queue_status: None,
+            },
+        }
+    }
+
+    /// Waits for the next batch of messages.
+    pub async fn wait(&mut self) -> windows::core::Result<MessageIterator<'_>> {
+        let messages = poll_fn(|cx| self.poll_batch(cx)).await?;
+        Ok(self.drain_eagerly(messages))
+    }
+
+    /// Drains the batch into the waiter's buffer right away if the waiter
+    /// [drains eagerly](MessageWaiterBuilder::eager_drain).
+    fn drain_eagerly(&mut self, messages: MessageIterator<'static>) -> MessageIterator<'_> {
+        if self.options.eager_drain {
+            messages.drain_now_into(&mut self.buffer)
+        } else {
+            messages
+        }
+    }
+
+    /// Waits for the next batch of messages and drains all of them into the waiter's buffer.
+    ///
+    /// The returned slice reflects the queue at the time of draining, rather than at the time of iterating. The buffer
+    /// is cleared, not reallocated, for every batch, so once it has grown to the size of the largest batch, draining
+    /// doesn't allocate anymore. Compared to collecting every batch into a new `Vec`, this saves one allocation (and
+    /// usually a few reallocations while growing) per batch; use
+    /// [`MessageWaiterBuilder::buffer_capacity`] to avoid growing the buffer altogether.
+    pub async fn wait_snapshot(&mut self) -> windows::core::Result<&[MSG]> {
+        let mut messages = poll_fn(|cx| self.poll_batch(cx)).await?;
+
+        self.buffer.clear();
+        messages.drain_into(&mut self.buffer)?;
+
+        Ok(&self.buffer)
+    }
+
+    /// Waits for the next batch of messages and lends them in chunks of up to `chunk_size`, drained into the waiter's
+    /// buffer.
+    ///
+    /// This is the allocation-free counterpart of iterating the batch: the buffer is shared with
+    /// [`MessageWaiter::wait_snapshot`] and reused for every chunk and batch, see [`LendingMessages`].
+    ///
+    /// [`LendingMessages`]: crate::LendingMessages
+    #[cfg(feature = "lending")]
+    pub async fn wait_lending(
+        &mut self,
+        chunk_size: std::num::NonZeroUsize,
+    ) -> windows::core::Result<crate::LendingMessages<'_>> {
+        let messages = poll_fn(|cx| self.poll_batch(cx)).await?;
+        Ok(messages.lend_into(&mut self.buffer, chunk_size))
+    }
+
+    /// Waits for the next batch of messages and appends all of them to `buffer`, returning how many were appended.
+    ///
+    /// See [`MessageIterator::drain_into`].
+    pub async fn drain_into(&mut self, buffer: &mut Vec<MSG>) -> windows::core::Result<usize> {
+        poll_fn(|cx| self.poll_batch(cx)).await?.drain_into(buffer)
+    }
+
+    /// Waits for the next message and removes only that one from the queue.
+    ///
+    /// If the wait is woken up without a message to remove, e.g. because `PeekMessageW` only dispatched sent
+    /// messages, the wait is re-armed. Even if the waiter [drains eagerly](MessageWaiterBuilder::eager_drain), the
+    /// remaining messages stay queued for the next call.
+    pub async fn next_message(&mut self) -> windows::core::Result<MSG> {
+        loop {
+            if let Some(msg) = poll_fn(|cx| self.poll_batch(cx)).await?.next() {
+                return Ok(msg);
+            }
+        }
+    }
+}
+
+/// Waits for the next message and removes only that one from the queue, leaving the rest for the next call.
+pub async fn next_message(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+) -> windows::core::Result<MSG> {
+    MessageWaiter::new(queue_status_flags, wait_flags)?
+        .next_message()
+        .await
+}
+
+/// Waits for the next message and removes only that one from the queue, or returns `None` if no message arrives
+/// within `timeout`.
+///
+/// The timeout is passed to the threadpool wait, so no timer is involved. If the wait is woken up without a message to
+/// remove, it's re-armed for the time that is left rather than for the full `timeout`, so spurious wake-ups can't
+/// extend the deadline. A message that is found when the deadline has just passed is still returned.
+pub async fn recv_one_timeout(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+    timeout: Duration,
+) -> windows::core::Result<Option<MSG>> {
+    let mut waiter = MessageWaiter::new(queue_status_flags, wait_flags)?;
+    // A timeout too large to represent never expires.
+    waiter.options.deadline = Instant::now().checked_add(timeout);
+
+    loop {
+        if let Some(msg) = waiter.wait().await?.next() {
+            return Ok(Some(msg));
+        }
+
+        if waiter.options.deadline_passed() {
+            return Ok(None);
+        }
+    }
+}
+
+/// Waits for the next `WM_HOTKEY` message and removes it from the queue.
+///
+/// Hotkeys registered via `RegisterHotKey` without a window are associated with the registering thread, so this has
+/// to run on that thread; hotkeys of other threads never wake it up. The hotkey id is in the message's `wParam`.
+/// Other messages are left in the queue.
+pub async fn wait_for_hotkey() -> windows::core::Result<MSG> {
+    MessageWaiter::builder(QS_HOTKEY, MWMO_NONE)
+        .message_filter(WM_HOTKEY..=WM_HOTKEY)
+        .build()?
+        .next_message()
+        .await
+}
+
+/// Configures a [`MessageWaiter`].
+pub struct MessageWaiterBuilder {
+    queue_status_flags: QUEUE_STATUS_FLAGS,
+    wait_flags: MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS,
+    options: WaitOptions,
+    wake_hook: Option<WakeHook>,
+    mask_change_policy: MaskChangePolicy,
+    buffer_capacity: usize,
+    peek_qualifiers: PEEK_MESSAGE_REMOVE_TYPE,
+    require_readonly_queue_status: bool,
+    input_event: Option<HANDLE>,
+}
+
+impl MessageWaiterBuilder {
+    /// Sets how often creating the threadpool wait is retried when it fails, e.g. due to resource exhaustion.
+    ///
+    /// Retries back off for a few milliseconds on a threadpool timer, leaving the polling thread free in the meantime.
+    /// Once they are exhausted, the thread's cached wait is used if no other wait on this thread holds it; otherwise
+    /// the error is returned. The cached wait is created when creating a wait fails for the first time.
+    pub fn create_wait_retries(mut self, retries: u32) -> Self {
+        self.options.create_wait_retries = retries;
+        self
+    }
+
+    /// Restricts waiting and draining to messages within `range`, like the filter arguments of `PeekMessageW`.
+    ///
+    /// The wake mask can only express message categories, so the waiter re-arms itself if it's woken up only by
+    /// messages outside of the range. Those messages are never drained and stay queued, so the waiter is re-armed
+    /// without `MWMO_INPUTAVAILABLE` to not wake up for them again.
+    pub fn message_filter(mut self, range: RangeInclusive<u32>) -> Self {
+        self.options.message_filter.set_range(range);
+        self
+    }
+
+    /// Restricts waiting and draining to the message categories selected by the `PM_QS_*` qualifiers, e.g.
+    /// `PM_QS_POSTMESSAGE` to leave input and paint messages queued.
+    ///
+    /// [`MessageWaiterBuilder::build`] fails with `E_INVALIDARG` if `qualifiers` contains anything but `PM_QS_*`
+    /// flags.
+    pub fn peek_qualifiers(mut self, qualifiers: PEEK_MESSAGE_REMOVE_TYPE) -> Self {
+        self.peek_qualifiers = qualifiers;
+        self
+    }
+
+    /// Sets how long releasing a wait blocks for a threadpool callback that is still running, defaulting to
+    /// [`DEFAULT_CALLBACK_TIMEOUT`](crate::DEFAULT_CALLBACK_TIMEOUT).
+    ///
+    /// If the callback doesn't finish in time, e.g. because a wake hook or a waker is stuck, the threadpool wait and
+    /// the input event configuration are leaked and counted by [`leaked_waits`](crate::leaked_waits). This leaves the
+    /// thread's wake mask set, but a leak is preferable to a deadlock during shutdown.
+    pub fn callback_timeout(mut self, timeout: Duration) -> Self {
+        self.options.callback_timeout = timeout;
+        self
+    }
+
+    /// Sets whether the queue's wait completion packet is cancelled while waiting and reassociated afterwards, which
+    /// is the default.
+    ///
+    /// Starting with Windows 10, the message queue can be associated with an I/O completion port through a wait
+    /// completion packet, which consumes the signal of the input event. While the packet is associated, a wait on the
+    /// input event may never complete, so the packet is cancelled for the duration of the wait, like
+    /// `MsgWaitForMultipleObjectsEx` does. Earlier versions don't have the packet and ignore this.
+    ///
+    /// Only disable this if no completion port is ever associated with the queue, or if another component already
+    /// cancels the packet while the wait is armed; otherwise waits can hang indefinitely.
+    pub fn manage_completion_packet(mut self, manage: bool) -> Self {
+        self.options.manage_completion_packet = manage;
+        self
+    }
+
+    /// Drains the queue as soon as the wait resolves, rather than while iterating.
+    ///
+    /// By default, the returned [`MessageIterator`] removes messages lazily, so it reflects the queue at the time of
+    /// iterating. With eager draining, all matching messages are removed right when the wait resolves, which makes the
+    /// batch independent of what happens between the wake and the iteration. The waiter drains into its buffer, see
+    /// [`MessageWaiterBuilder::buffer_capacity`], while a standalone future allocates a new one for every batch.
+    ///
+    /// `MessageWaiter::wait_lending` always drains a chunk at a time, as the buffer holds the current chunk.
+    pub fn eager_drain(mut self, eager: bool) -> Self {
+        self.options.eager_drain = eager;
+        self
+    }
+
+    /// Sets what polling does if another wait is already armed on this thread, see [`OverlapPolicy`]. Overlapping
+    /// waits are allowed by default.
+    pub fn overlap_policy(mut self, policy: OverlapPolicy) -> Self {
+        self.options.overlap_policy = policy;
+        self
+    }
+
+    /// Makes each batch report how many new mouse moves the queue status announced versus how many `WM_MOUSEMOVE`
+    /// messages were drained, see [`MessageIterator::mouse_move_stats`].
+    ///
+    /// This costs an extra queue status reading per wake, which is taken before any messages are drained, as
+    /// retrieving messages clears the "added" bits. With [`QueueStatusBackend::Mutating`], the reading clears them
+    /// as well.
+    pub fn track_mouse_moves(mut self, track: bool) -> Self {
+        self.options.track_mouse_moves = track;
+        self
+    }
+
+    /// Sets what [`MessageWaiter::set_mask`] does while a wait is pending, see [`MaskChangePolicy`]. Changes are
+    /// deferred by default.
+    pub fn mask_change_policy(mut self, policy: MaskChangePolicy) -> Self {
+        self.mask_change_policy = policy;
+        self
+    }
+
+    /// Sets how the wait is armed, defaulting to [`Backend::Threadpool`].
+    pub fn backend(mut self, backend: Backend) -> Self {
+        self.options.backend = backend;
+        self
+    }
+
+    /// Busy-checks the queue for up to `budget` before arming the wait, which is disabled by default.
+    ///
+    /// Arming the wait costs a threadpool wait and, once a message arrives, a context switch to the threadpool and
+    /// back. If messages are expected imminently, spinning avoids both and reduces the latency of the wake-up. While
+    /// spinning, the future wakes itself and returns `Pending` after every check, so the executor keeps polling it
+    /// and a CPU core stays fully busy for the duration of the budget, for every batch that doesn't arrive right
+    /// away. Keep the budget in the range of microseconds, and only use it where latency matters more than power.
+    pub fn spin_budget(mut self, budget: Option<Duration>) -> Self {
+        self.options.spin_budget = budget;
+        self
+    }
+
+    /// Processes the messages sent by other threads whenever a wait resolves, instead of retrieving posted messages.
+    ///
+    /// The waiter has to wait for `QS_SENDMESSAGE` only, otherwise [`MessageWaiterBuilder::build`] fails with
+    /// `E_INVALIDARG`. Each batch is an iterator that processes the pending sent messages like
+    /// [`process_sent_messages`](crate::process_sent_messages) and yields nothing, leaving the posted messages queued.
+    /// The message filter doesn't apply to sent messages and is ignored.
+    pub fn sent_messages_only(mut self, sent_only: bool) -> Self {
+        self.options.sent_only = sent_only;
+        self
+    }
+
+    /// Makes [`MessageWaiterBuilder::build`] fail with `ERROR_PROC_NOT_FOUND` if the queue status can't be read
+    /// without side effects, see [`QueueStatusBackend::Mutating`].
+    pub fn require_readonly_queue_status(mut self, require: bool) -> Self {
+        self.require_readonly_queue_status = require;
+        self
+    }
+
+    /// Waits on an input event that the caller has already configured, rather than configuring the thread's input
+    /// event for every wait.
+    ///
+    /// See [`InputEventFuture::from_raw_parts`]. [`MessageWaiterBuilder::build`] fails with `E_INVALIDARG` if
+    /// `input_event` is null.
+    ///
+    /// # Safety
+    ///
+    /// See [`InputEventFuture::from_raw_parts`]. The contract applies to the thread that polls the waiter, for as long
+    /// as the waiter lives.
+    pub unsafe fn input_event(mut self, input_event: HANDLE) -> Self {
+        self.input_event = Some(input_event);
+        self
+    }
+
+    /// Preallocates the buffer used by [`MessageWaiter::wait_snapshot`] and by
+    /// [eager draining](MessageWaiterBuilder::eager_drain) for `capacity` messages.
+    pub fn buffer_capacity(mut self, capacity: usize) -> Self {
+        self.buffer_capacity = capacity;
+        self
+    }
+
+    /// Registers a hook that is called on the threadpool thread right after each wait completes.
+    ///
+    /// # Safety
+    ///
+    /// See [`InputEventFuture::with_wake_hook`]. The hook must not block, access the message queue of the waiting
+    /// thread, or call into this crate.
+    pub unsafe fn wake_hook(mut self, wake_hook: impl FnMut() + Send + 'static) -> Self {
+        self.wake_hook = Some(Arc::new(Mutex::new(wake_hook)));
+        self
+    }
+
+    pub fn build(mut self) -> windows::core::Result<MessageWaiter> {
+        let (queue_status_flags, wait_flags) =
+            narrow_flags(self.queue_status_flags, self.wait_flags)?;
+        self.options
+            .message_filter
+            .set_qualifiers(self.peek_qualifiers)?;
+
+        if let Some(input_event) = self.input_event {
+            self.options.set_external_input_event(input_event)?;
+        }
+
+        if self.options.sent_only && self.queue_status_flags != QS_SENDMESSAGE {
+            return Err(windows::core::Error::new(
+                E_INVALIDARG,
+                "processing sent messages only requires waiting for QS_SENDMESSAGE only",
+            ));
+        }
+
+        if self.require_readonly_queue_status
+            && queue_status_backend() != QueueStatusBackend::Readonly
+        {
+            return Err(windows::core::Error::new(
+                ERROR_PROC_NOT_FOUND.to_hresult(),
+                "NtUserGetQueueStatusReadonly is not available on this system",
+            ));
+        }
+
+        Ok(MessageWaiter {
+            queue_status_flags,
+            wait_flags,
+            options: self.options,
+            wake_hook: self.wake_hook,
+            mask_change_policy: self.mask_change_policy,
+            future: None,
+            buffer: Vec::with_capacity(self.buffer_capacity),
+        })
+    }
+}
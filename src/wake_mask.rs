@@ -0,0 +1,41 @@
+use nt_user_call::functions::{NtUserClearWakeMask, NtUserGetInputEvent};
+use windows::Win32::UI::WindowsAndMessaging::{
+    MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, QUEUE_STATUS_FLAGS,
+};
+
+use crate::{
+    Syscall,
+    msg_future::{make_dword, narrow_flags},
+};
+
+/// Sets the thread's wake mask, which selects the messages that signal the thread's input event.
+///
+/// This is the first half of what `MsgWaitForMultipleObjectsEx` does before waiting, and is only needed when
+/// coordinating with message-waiting code that waits on the input event itself. [`InputEventFuture`] sets the wake
+/// mask when it arms its wait and clears it again once the wait is done, so calling this while a wait is pending
+/// replaces the mask that wait relies on.
+///
+/// [`InputEventFuture`]: crate::InputEventFuture
+pub fn set_wake_mask(
+    queue_status_flags: impl Into<QUEUE_STATUS_FLAGS>,
+    wait_flags: impl Into<MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS>,
+) -> windows::core::Result<()> {
+    let (queue_status_flags, wait_flags) =
+        narrow_flags(queue_status_flags.into(), wait_flags.into())?;
+
+    unsafe { NtUserGetInputEvent(make_dword(queue_status_flags, wait_flags)) }
+        .map_err(|error| Syscall::NtUserGetInputEvent.error(error))?;
+
+    Ok(())
+}
+
+/// Clears the thread's wake mask, so that no message signals the thread's input event anymore.
+///
+/// [`InputEventFuture`](crate::InputEventFuture) does this automatically once its wait is done, which also undoes a
+/// mask set via [`set_wake_mask`]. Clearing the mask while a wait is pending keeps that wait from being woken up by
+/// new messages.
+pub fn clear_wake_mask() -> windows::core::Result<()> {
+    unsafe { NtUserClearWakeMask() }.map_err(|error| Syscall::NtUserClearWakeMask.error(error))?;
+
+    Ok(())
+}
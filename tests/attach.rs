@@ -0,0 +1,84 @@
+use std::{future::poll_fn, mem::MaybeUninit, pin::pin, sync::mpsc, task::Poll};
+
+use async_messages::{AttachedInput, wait_for_attached_input};
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::{AttachThreadInput, GetCurrentThreadId},
+    UI::WindowsAndMessaging::{
+        MWMO_NONE, PM_NOREMOVE, PeekMessageW, PostThreadMessageW, QS_ALLINPUT, WM_USER,
+    },
+};
+
+fn in_new_thread(f: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(f).join().unwrap();
+}
+
+/// Polls the wait once so that it's armed, then lets `helper` attach or detach, and returns what woke the wait up.
+async fn arm_then(
+    helper: &mpsc::Sender<bool>,
+    done: &mpsc::Receiver<()>,
+    attach: bool,
+) -> AttachedInput {
+    let mut wait = pin!(wait_for_attached_input(QS_ALLINPUT, MWMO_NONE));
+
+    poll_fn(|cx| {
+        assert!(wait.as_mut().poll(cx).is_pending());
+        Poll::Ready(())
+    })
+    .await;
+
+    helper.send(attach).unwrap();
+    done.recv().unwrap();
+
+    wait.await.unwrap()
+}
+
+#[test]
+fn attaching_input_wakes_the_wait() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let ui_thread = unsafe { GetCurrentThreadId() };
+
+        let (requests, received) = mpsc::channel::<bool>();
+        let (done, finished) = mpsc::channel();
+        let helper = std::thread::spawn(move || {
+            // AttachThreadInput needs both threads to have a message queue.
+            let mut msg = MaybeUninit::uninit();
+            _ = unsafe { PeekMessageW(msg.as_mut_ptr(), None, 0, 0, PM_NOREMOVE) };
+
+            for attach in received {
+                unsafe { AttachThreadInput(GetCurrentThreadId(), ui_thread, attach) }
+                    .ok()
+                    .unwrap();
+                done.send(()).unwrap();
+            }
+        });
+
+        runtime.block_on(async {
+            let woken = arm_then(&requests, &finished, true).await;
+            assert!(matches!(woken, AttachedInput::QueueAttached));
+
+            // Messages still resolve the wait as usual while the queues are merged.
+            unsafe {
+                PostThreadMessageW(ui_thread, WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+            }
+
+            match wait_for_attached_input(QS_ALLINPUT, MWMO_NONE)
+                .await
+                .unwrap()
+            {
+                AttachedInput::Messages(messages) => {
+                    assert_eq!(messages.map(|msg| msg.wParam.0).collect::<Vec<_>>(), [1]);
+                }
+                AttachedInput::QueueAttached => panic!("woken up without the posted message"),
+            }
+
+            let woken = arm_then(&requests, &finished, false).await;
+            assert!(matches!(woken, AttachedInput::QueueAttached));
+        });
+
+        drop(requests);
+        helper.join().unwrap();
+    });
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "tokio")]
+
+use std::time::{Duration, Instant};
+
+use async_messages::{WaitOutcome, wait_for_messages_until};
+use tokio::runtime::Builder;
+use tokio_util::sync::CancellationToken;
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER},
+};
+
+fn in_new_thread(f: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(f).join().unwrap();
+}
+
+#[test]
+fn cancelling_aborts_pending_wait() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+        let token = CancellationToken::new();
+
+        let canceller = std::thread::spawn({
+            let token = token.clone();
+            move || {
+                std::thread::sleep(Duration::from_millis(100));
+                token.cancel();
+                Instant::now()
+            }
+        });
+
+        let outcome = runtime.block_on(async {
+            tokio::time::timeout(
+                Duration::from_secs(5),
+                wait_for_messages_until(&token, QS_ALLPOSTMESSAGE, MWMO_NONE),
+            )
+            .await
+            .expect("the wait wasn't cancelled")
+            .unwrap()
+        });
+        let resolved_at = Instant::now();
+
+        assert!(matches!(outcome, WaitOutcome::Cancelled));
+        assert!(resolved_at - canceller.join().unwrap() < Duration::from_secs(1));
+
+        // The cancelled wait has been torn down, so a new one can be armed and completes normally.
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+        }
+
+        let outcome = runtime
+            .block_on(wait_for_messages_until(
+                &CancellationToken::new(),
+                QS_ALLPOSTMESSAGE,
+                MWMO_NONE,
+            ))
+            .unwrap();
+
+        let WaitOutcome::Messages(messages) = outcome else {
+            panic!("the wait was cancelled");
+        };
+        assert_eq!(messages.count(), 1);
+    });
+}
+
+#[test]
+fn cancelled_token_takes_priority() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let token = CancellationToken::new();
+        token.cancel();
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+        }
+
+        let outcome = runtime
+            .block_on(wait_for_messages_until(
+                &token,
+                QS_ALLPOSTMESSAGE,
+                MWMO_NONE,
+            ))
+            .unwrap();
+        assert!(matches!(outcome, WaitOutcome::Cancelled));
+    });
+}
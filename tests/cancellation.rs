@@ -0,0 +1,118 @@
+use std::{future::poll_fn, sync::mpsc, task::Poll, time::Duration};
+
+use async_messages::{InputEventFuture, wait_for_messages};
+use nt_user_call::functions::{
+    NtUserCancelQueueEventCompletionPacket, NtUserClearWakeMask, NtUserGetInputEvent,
+    NtUserReassociateQueueEventCompletionPacket,
+};
+use tokio::{runtime::Builder, task::LocalSet};
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::{GetCurrentThreadId, SetEvent},
+    UI::WindowsAndMessaging::{MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER},
+};
+
+const ITERATIONS: usize = 1000;
+
+/// Runs `f` on a new thread, failing if it doesn't finish within `timeout`.
+fn with_deadline(timeout: Duration, f: impl FnOnce() + Send + 'static) {
+    let (tx, rx) = mpsc::channel();
+    let thread = std::thread::spawn(move || {
+        f();
+        _ = tx.send(());
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(()) => thread.join().unwrap(),
+        Err(mpsc::RecvTimeoutError::Disconnected) => thread.join().unwrap(),
+        Err(mpsc::RecvTimeoutError::Timeout) => panic!("deadlocked dropping a pending wait"),
+    }
+}
+
+#[test]
+fn drop_pending_wait_while_callback_fires() {
+    with_deadline(Duration::from_secs(30), || {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let local = LocalSet::new();
+
+        local.block_on(&runtime, async {
+            tokio::task::spawn_local(async {
+                // Configure the input event once and wait on it directly, so that the test controls when it's
+                // signaled instead of going through the message queue.
+                let input_event = unsafe { NtUserGetInputEvent(QS_ALLPOSTMESSAGE.0) }
+                    .map_err(windows::core::Error::from)
+                    .unwrap();
+                unsafe {
+                    _ = NtUserCancelQueueEventCompletionPacket();
+                }
+
+                for _ in 0..ITERATIONS {
+                    let mut future = Box::pin(unsafe {
+                        InputEventFuture::from_raw_parts(input_event, QS_ALLPOSTMESSAGE, MWMO_NONE)
+                            .unwrap()
+                    });
+
+                    poll_fn(|cx| {
+                        assert!(future.as_mut().poll(cx).is_pending());
+                        Poll::Ready(())
+                    })
+                    .await;
+
+                    // Schedules the threadpool callback right away, so that it races with the drop below.
+                    unsafe { SetEvent(input_event) }.unwrap();
+
+                    drop(future);
+                }
+
+                unsafe {
+                    NtUserClearWakeMask()
+                        .map_err(windows::core::Error::from)
+                        .unwrap();
+                    _ = NtUserReassociateQueueEventCompletionPacket();
+                }
+
+                // The thread's own waits still work after all the cancelled ones.
+                unsafe {
+                    PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0))
+                        .unwrap();
+                }
+
+                let messages = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+                    .unwrap()
+                    .await
+                    .unwrap()
+                    .collect::<Vec<_>>();
+                assert_eq!(messages.len(), 1);
+            })
+            .await
+            .unwrap();
+        });
+    });
+}
+
+#[test]
+fn message_posted_while_arming_wakes_wait() {
+    with_deadline(Duration::from_secs(30), || {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let thread_id = unsafe { GetCurrentThreadId() };
+
+        for _ in 0..ITERATIONS {
+            // Racing the post against the poll makes it land anywhere from before the fast path check to after the
+            // state has been set to Pending, including between arming the threadpool wait and setting the state.
+            let poster = std::thread::spawn(move || unsafe {
+                PostThreadMessageW(thread_id, WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+            });
+
+            let messages = runtime.block_on(async {
+                wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+                    .unwrap()
+                    .await
+                    .unwrap()
+                    .count()
+            });
+
+            poster.join().unwrap();
+            assert_eq!(messages, 1);
+        }
+    });
+}
@@ -0,0 +1,206 @@
+mod helpers;
+
+use async_messages::{
+    QuitCode, drain_until_empty, for_each_message, run_message_loop, run_message_loop_with,
+    run_message_loop_with_results, wait_for_quit,
+};
+use helpers::window::{create_window, register_window_class};
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{
+        DefWindowProcW, HWND_MESSAGE, MSG, MWMO_NONE, PM_NOREMOVE, PeekMessageW, PostMessageW,
+        PostQuitMessage, PostThreadMessageW, QS_ALLINPUT, QS_ALLPOSTMESSAGE, WM_USER,
+    },
+};
+
+const WM_RESULT: u32 = WM_USER + 5;
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    unsafe {
+        if msg == WM_RESULT {
+            PostQuitMessage(0);
+            return LRESULT(0x1234);
+        }
+
+        DefWindowProcW(hwnd, msg, wparam, lparam)
+    }
+}
+
+fn in_new_thread(f: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(f).join().unwrap();
+}
+
+#[test]
+fn quit_code_from_wparam() {
+    assert_eq!(QuitCode::from(WPARAM(0)), QuitCode(0));
+    assert_eq!(QuitCode::from(WPARAM(42)), QuitCode(42));
+    assert_eq!(QuitCode::from(WPARAM(-1isize as usize)), QuitCode(-1));
+    assert_eq!(
+        QuitCode::from(WPARAM(i32::MIN as isize as usize)),
+        QuitCode(i32::MIN)
+    );
+}
+
+#[test]
+fn negative_quit_code_round_trips() {
+    for exit_code in [-1, i32::MIN, i32::MAX] {
+        in_new_thread(move || {
+            let runtime = Builder::new_current_thread().build().unwrap();
+
+            unsafe { PostQuitMessage(exit_code) };
+
+            let quit_code = runtime
+                .block_on(run_message_loop(QS_ALLINPUT, MWMO_NONE))
+                .unwrap();
+            assert_eq!(quit_code, QuitCode(exit_code));
+        });
+    }
+}
+
+#[test]
+fn wait_for_quit_passes_other_messages() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(7), LPARAM(0)).unwrap();
+            PostQuitMessage(3);
+        }
+
+        let mut received = Vec::new();
+        let quit_code = runtime
+            .block_on(wait_for_quit(QS_ALLPOSTMESSAGE, MWMO_NONE, |msg| {
+                received.push((msg.message, msg.wParam.0))
+            }))
+            .unwrap();
+
+        assert_eq!(quit_code, QuitCode(3));
+        assert_eq!(received, [(WM_USER, 7)]);
+    });
+}
+
+#[test]
+fn thread_messages_reach_callback() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER + 1, WPARAM(0x1234), LPARAM(0))
+                .unwrap();
+            PostQuitMessage(0);
+        }
+
+        let mut received = Vec::new();
+        runtime
+            .block_on(run_message_loop_with(QS_ALLPOSTMESSAGE, MWMO_NONE, |msg| {
+                received.push((msg.hwnd.0.is_null(), msg.message, msg.wParam.0))
+            }))
+            .unwrap();
+
+        assert_eq!(received, [(true, WM_USER + 1, 0x1234)]);
+    });
+}
+
+#[test]
+fn drain_until_empty_handles_messages_posted_by_dispatch() {
+    in_new_thread(|| unsafe {
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(3), LPARAM(0)).unwrap();
+
+        let mut received = Vec::new();
+        drain_until_empty(QS_ALLPOSTMESSAGE, MWMO_NONE, |msg| {
+            received.push(msg.wParam.0);
+
+            if msg.wParam.0 > 0 {
+                PostThreadMessageW(
+                    GetCurrentThreadId(),
+                    WM_USER,
+                    WPARAM(msg.wParam.0 - 1),
+                    LPARAM(0),
+                )
+                .unwrap();
+            }
+        })
+        .unwrap();
+
+        assert_eq!(received, [3, 2, 1, 0]);
+
+        let mut msg = MSG::default();
+        assert!(!PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE).as_bool());
+    });
+}
+
+#[test]
+fn for_each_message_handles_messages_posted_by_handler() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(3), LPARAM(0)).unwrap();
+        }
+
+        let mut received = Vec::new();
+        let quit_code = runtime
+            .block_on(for_each_message(QS_ALLPOSTMESSAGE, MWMO_NONE, |msg| {
+                received.push(msg.wParam.0);
+
+                async move {
+                    tokio::task::yield_now().await;
+
+                    unsafe {
+                        if msg.wParam.0 > 0 {
+                            PostThreadMessageW(
+                                GetCurrentThreadId(),
+                                WM_USER,
+                                WPARAM(msg.wParam.0 - 1),
+                                LPARAM(0),
+                            )
+                            .unwrap();
+                        } else {
+                            PostQuitMessage(5);
+                        }
+                    }
+                }
+            }))
+            .unwrap();
+
+        assert_eq!(quit_code, QuitCode(5));
+        assert_eq!(received, [3, 2, 1, 0]);
+    });
+}
+
+#[test]
+fn dispatch_results_are_surfaced() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        let window_class = register_window_class(Some(window_proc)).unwrap();
+        let window = create_window(&window_class, Some(HWND_MESSAGE)).unwrap();
+
+        unsafe {
+            PostMessageW(Some(**window), WM_RESULT, WPARAM(0), LPARAM(0)).unwrap();
+        }
+
+        let mut results = Vec::new();
+        runtime
+            .block_on(run_message_loop_with_results(
+                QS_ALLINPUT,
+                MWMO_NONE,
+                |_| {},
+                |msg, result| {
+                    if msg.message == WM_RESULT {
+                        results.push(result);
+                    }
+                },
+            ))
+            .unwrap();
+
+        assert_eq!(results, [LRESULT(0x1234)]);
+    });
+}
@@ -0,0 +1,116 @@
+use async_messages::{
+    MWMO_QUEUEATTACH, MessageWaiter, QueueStatusBackend, QueueStatusFlags,
+    SUPPORTED_QUEUE_STATUS_FLAGS, WaitFlags, queue_status_backend, wait_for_messages,
+};
+use windows::Win32::{
+    Foundation::E_INVALIDARG,
+    UI::WindowsAndMessaging::{
+        MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS, MWMO_INPUTAVAILABLE, MWMO_NONE, QS_ALLEVENTS,
+        QS_ALLINPUT, QS_ALLPOSTMESSAGE, QS_HOTKEY, QS_INPUT, QS_KEY, QS_MOUSE, QS_MOUSEBUTTON,
+        QS_MOUSEMOVE, QS_PAINT, QS_POINTER, QS_POSTMESSAGE, QS_RAWINPUT, QS_SENDMESSAGE, QS_TIMER,
+        QS_TOUCH, QUEUE_STATUS_FLAGS,
+    },
+};
+
+#[test]
+fn documented_flags_round_trip() {
+    for flags in [
+        QS_KEY,
+        QS_MOUSEMOVE,
+        QS_MOUSEBUTTON,
+        QS_POSTMESSAGE,
+        QS_TIMER,
+        QS_PAINT,
+        QS_SENDMESSAGE,
+        QS_HOTKEY,
+        QS_ALLPOSTMESSAGE,
+        QS_RAWINPUT,
+        QS_TOUCH,
+        QS_POINTER,
+        QS_MOUSE,
+        QS_INPUT,
+        QS_ALLEVENTS,
+        QS_ALLINPUT,
+    ] {
+        let waiter = MessageWaiter::new(flags, MWMO_NONE).unwrap();
+        assert_eq!(waiter.explain_wait_state().queue_status_flags, flags);
+    }
+
+    for flags in [MWMO_INPUTAVAILABLE, MWMO_QUEUEATTACH] {
+        let waiter = MessageWaiter::new(QS_ALLINPUT, flags).unwrap();
+        assert_eq!(waiter.explain_wait_state().wait_flags, flags);
+    }
+}
+
+#[test]
+fn rejects_flags_wider_than_16_bits() {
+    let error = MessageWaiter::new(QUEUE_STATUS_FLAGS(0x10000), MWMO_NONE)
+        .err()
+        .unwrap();
+    assert_eq!(error.code(), E_INVALIDARG);
+
+    let error = MessageWaiter::new(QS_ALLINPUT, MSG_WAIT_FOR_MULTIPLE_OBJECTS_EX_FLAGS(0x10000))
+        .err()
+        .unwrap();
+    assert_eq!(error.code(), E_INVALIDARG);
+}
+
+#[test]
+fn builders_match_raw_flags() {
+    assert_eq!(
+        QueueStatusFlags::new()
+            .input()
+            .timer()
+            .post_message()
+            .build(),
+        QS_INPUT | QS_TIMER | QS_POSTMESSAGE
+    );
+    assert_eq!(QueueStatusFlags::new().all_input().build(), QS_ALLINPUT);
+    assert_eq!(
+        WaitFlags::new().input_available().queue_attach().build(),
+        MWMO_INPUTAVAILABLE | MWMO_QUEUEATTACH
+    );
+    assert_eq!(WaitFlags::new().build(), MWMO_NONE);
+
+    let waiter = MessageWaiter::new(
+        QueueStatusFlags::new().key().hotkey(),
+        WaitFlags::new().input_available(),
+    )
+    .unwrap();
+    let snapshot = waiter.explain_wait_state();
+    assert_eq!(snapshot.queue_status_flags, QS_KEY | QS_HOTKEY);
+    assert_eq!(snapshot.wait_flags, MWMO_INPUTAVAILABLE);
+}
+
+#[test]
+fn rejects_reserved_flags() {
+    const QS_EVENT: QUEUE_STATUS_FLAGS = QUEUE_STATUS_FLAGS(0x2000);
+
+    assert_eq!(SUPPORTED_QUEUE_STATUS_FLAGS.0 & QS_EVENT.0, 0);
+
+    let error = wait_for_messages(QS_ALLINPUT | QS_EVENT, MWMO_NONE)
+        .err()
+        .unwrap();
+    assert_eq!(error.code(), E_INVALIDARG);
+    assert!(error.message().contains("reserved"));
+
+    assert!(wait_for_messages(SUPPORTED_QUEUE_STATUS_FLAGS, MWMO_NONE).is_ok());
+
+    // Touch and pointer input are documented, even though the bindings' QS_ALLINPUT doesn't include them.
+    for flags in [QS_TOUCH, QS_POINTER, QS_ALLINPUT | QS_TOUCH | QS_POINTER] {
+        assert_eq!(SUPPORTED_QUEUE_STATUS_FLAGS.0 & flags.0, flags.0);
+        assert!(wait_for_messages(flags, MWMO_NONE).is_ok());
+    }
+}
+
+#[test]
+fn require_readonly_queue_status_matches_backend() {
+    let result = MessageWaiter::builder(QS_ALLINPUT, MWMO_NONE)
+        .require_readonly_queue_status(true)
+        .build();
+
+    match queue_status_backend() {
+        QueueStatusBackend::Readonly => assert!(result.is_ok()),
+        QueueStatusBackend::Mutating => assert!(result.is_err()),
+    }
+}
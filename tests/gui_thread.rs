@@ -0,0 +1,75 @@
+use async_messages::GuiThread;
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{PostMessageW, SendMessageW, WM_USER},
+};
+
+#[test]
+fn forwards_messages_from_gui_thread() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    let mut gui_thread = GuiThread::spawn().unwrap();
+    assert_ne!(gui_thread.thread_id(), unsafe { GetCurrentThreadId() });
+
+    unsafe {
+        PostMessageW(Some(gui_thread.hwnd()), WM_USER + 5, WPARAM(1), LPARAM(2)).unwrap();
+    }
+
+    let message = runtime.block_on(async {
+        loop {
+            let messages = gui_thread.recv_messages().await.unwrap();
+            if let Some(message) = messages.into_iter().find(|msg| msg.message == WM_USER + 5) {
+                return message;
+            }
+        }
+    });
+
+    assert_eq!(message.hwnd(), gui_thread.hwnd());
+    assert_eq!(message.wparam, WPARAM(1));
+    assert_eq!(message.lparam, LPARAM(2));
+
+    gui_thread.join().unwrap();
+}
+
+#[test]
+fn forwards_sent_messages() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let mut gui_thread = GuiThread::spawn().unwrap();
+
+    // Sent messages, like WM_DEVICECHANGE, bypass the queue and only reach the window procedure.
+    unsafe {
+        SendMessageW(
+            gui_thread.hwnd(),
+            WM_USER + 6,
+            Some(WPARAM(3)),
+            Some(LPARAM(4)),
+        );
+    }
+
+    let message = runtime.block_on(async {
+        loop {
+            let messages = gui_thread.recv_messages().await.unwrap();
+            if let Some(message) = messages.into_iter().find(|msg| msg.message == WM_USER + 6) {
+                return message;
+            }
+        }
+    });
+
+    assert_eq!(message.hwnd(), gui_thread.hwnd());
+    assert_eq!(message.wparam, WPARAM(3));
+    assert_eq!(message.lparam, LPARAM(4));
+
+    gui_thread.join().unwrap();
+}
+
+#[test]
+fn drop_stops_thread() {
+    let gui_thread = GuiThread::spawn().unwrap();
+    let hwnd = gui_thread.hwnd();
+    drop(gui_thread);
+
+    // The window has been destroyed along with the thread.
+    assert!(unsafe { PostMessageW(Some(hwnd), WM_USER, WPARAM(0), LPARAM(0)) }.is_err());
+}
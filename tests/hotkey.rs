@@ -0,0 +1,76 @@
+use std::mem::size_of;
+
+use async_messages::wait_for_hotkey;
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::{
+        Input::KeyboardAndMouse::{
+            INPUT, INPUT_0, INPUT_KEYBOARD, KEYBD_EVENT_FLAGS, KEYBDINPUT, KEYEVENTF_KEYUP,
+            MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, RegisterHotKey, SendInput,
+            UnregisterHotKey, VIRTUAL_KEY, VK_F24, VK_LCONTROL, VK_LMENU, VK_LSHIFT,
+        },
+        WindowsAndMessaging::{MSG, PM_REMOVE, PeekMessageW, PostThreadMessageW, WM_USER},
+    },
+};
+
+const HOTKEY_ID: i32 = 0x4242;
+
+fn key(vk: VIRTUAL_KEY, flags: KEYBD_EVENT_FLAGS) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: vk,
+                dwFlags: flags,
+                ..Default::default()
+            },
+        },
+    }
+}
+
+#[test]
+fn wakes_on_registered_hotkey() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    unsafe {
+        RegisterHotKey(
+            None,
+            HOTKEY_ID,
+            MOD_CONTROL | MOD_ALT | MOD_SHIFT | MOD_NOREPEAT,
+            VK_F24.0.into(),
+        )
+        .unwrap();
+
+        // Left queued by the filter.
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+
+        let none = KEYBD_EVENT_FLAGS(0);
+        let inputs = [
+            key(VK_LCONTROL, none),
+            key(VK_LMENU, none),
+            key(VK_LSHIFT, none),
+            key(VK_F24, none),
+            key(VK_F24, KEYEVENTF_KEYUP),
+            key(VK_LSHIFT, KEYEVENTF_KEYUP),
+            key(VK_LMENU, KEYEVENTF_KEYUP),
+            key(VK_LCONTROL, KEYEVENTF_KEYUP),
+        ];
+        assert_eq!(
+            SendInput(&inputs, size_of::<INPUT>() as i32),
+            inputs.len() as u32
+        );
+    }
+
+    let msg = runtime.block_on(wait_for_hotkey()).unwrap();
+    assert_eq!(msg.wParam.0, HOTKEY_ID as usize);
+
+    unsafe {
+        UnregisterHotKey(None, HOTKEY_ID).unwrap();
+
+        let mut msg = MSG::default();
+        assert!(PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE).as_bool());
+        assert_eq!(msg.message, WM_USER);
+    }
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "lending")]
+
+use std::num::NonZeroUsize;
+
+use async_messages::{MessageWaiter, wait_for_messages};
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{MSG, MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER},
+};
+
+fn post(wparams: impl IntoIterator<Item = usize>) {
+    for wparam in wparams {
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(wparam), LPARAM(0)).unwrap();
+        }
+    }
+}
+
+#[test]
+fn lends_chunks_from_reused_buffer() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let mut waiter = MessageWaiter::new(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+    let chunk_size = NonZeroUsize::new(2).unwrap();
+
+    let mut buffer_address = None;
+
+    for batch in 0..2 {
+        post(batch * 10..batch * 10 + 5);
+
+        runtime.block_on(async {
+            let mut messages = waiter.wait_lending(chunk_size).await.unwrap();
+
+            let mut chunks = Vec::new();
+            while let Some(chunk) = messages.next() {
+                // The buffer has room for a full chunk from the start and is never reallocated.
+                assert_eq!(
+                    *buffer_address.get_or_insert(chunk.as_ptr()),
+                    chunk.as_ptr()
+                );
+                chunks.push(chunk.iter().map(|msg| msg.wParam.0).collect::<Vec<_>>());
+            }
+
+            let first = batch * 10;
+            assert_eq!(
+                chunks,
+                [
+                    vec![first, first + 1],
+                    vec![first + 2, first + 3],
+                    vec![first + 4]
+                ]
+            );
+        });
+    }
+}
+
+#[test]
+fn lend_into_caller_buffer() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+    post(0..3);
+
+    let mut buffer = Vec::<MSG>::new();
+    runtime.block_on(async {
+        let mut messages = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .await
+            .unwrap()
+            .lend_into(&mut buffer, NonZeroUsize::new(8).unwrap());
+
+        assert_eq!(messages.next().map(<[MSG]>::len), Some(3));
+        assert!(messages.next().is_none());
+    });
+
+    assert!(buffer.capacity() >= 8);
+}
@@ -0,0 +1,34 @@
+#![cfg(feature = "macros")]
+
+use async_messages::{QuitCode, message_loop};
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{
+        MSG, PostQuitMessage, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER,
+    },
+};
+
+thread_local! {
+    static RECEIVED: std::cell::RefCell<Vec<usize>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+#[message_loop(queue_status = QS_ALLPOSTMESSAGE, translate = false, dispatch = false)]
+async fn handle(msg: MSG) {
+    RECEIVED.with_borrow_mut(|received| received.push(msg.wParam.0));
+}
+
+#[test]
+fn message_loop_calls_body_until_quit() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    unsafe {
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(2), LPARAM(0)).unwrap();
+        PostQuitMessage(4);
+    }
+
+    assert_eq!(runtime.block_on(handle()).unwrap(), QuitCode(4));
+    RECEIVED.with_borrow(|received| assert_eq!(received, &[1, 2]));
+}
@@ -0,0 +1,118 @@
+#![cfg(feature = "test-util")]
+
+use async_messages::{MessageIterator, MouseMoveStats, ScriptedMessages};
+use windows::Win32::{
+    Foundation::WPARAM,
+    UI::WindowsAndMessaging::{MSG, WM_KEYDOWN, WM_MOUSEMOVE, WM_TIMER, WM_USER},
+};
+
+fn msg(message: u32, wparam: usize) -> MSG {
+    MSG {
+        message,
+        wParam: WPARAM(wparam),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn drains_scripted_messages_in_order() {
+    let mut source = ScriptedMessages::new([msg(WM_USER, 1), msg(WM_KEYDOWN, 2)]);
+    source.push(msg(WM_USER, 3));
+
+    let messages = MessageIterator::from_source(&mut source)
+        .map(|msg| msg.wParam.0)
+        .collect::<Vec<_>>();
+
+    assert_eq!(messages, [1, 2, 3]);
+    assert!(source.remaining().is_empty());
+}
+
+#[test]
+fn filtered_source_leaves_other_messages() {
+    let mut source = ScriptedMessages::new([
+        msg(WM_TIMER, 1),
+        msg(WM_USER, 2),
+        msg(WM_KEYDOWN, 3),
+        msg(WM_USER + 1, 4),
+    ]);
+
+    let messages = MessageIterator::from_source_filtered(&mut source, WM_USER..=WM_USER + 1)
+        .map(|msg| msg.wParam.0)
+        .collect::<Vec<_>>();
+
+    assert_eq!(messages, [2, 4]);
+    assert_eq!(
+        source
+            .remaining()
+            .iter()
+            .map(|msg| msg.message)
+            .collect::<Vec<_>>(),
+        [WM_TIMER, WM_KEYDOWN]
+    );
+}
+
+#[test]
+fn drain_into_appends_scripted_messages() {
+    let source = ScriptedMessages::new([msg(WM_USER, 1), msg(WM_MOUSEMOVE, 2)]);
+
+    let mut buffer = vec![msg(WM_KEYDOWN, 0)];
+    let mut messages = MessageIterator::from_source(source);
+    assert_eq!(messages.drain_into(&mut buffer).unwrap(), 2);
+    assert_eq!(
+        buffer.iter().map(|msg| msg.wParam.0).collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+
+    // Without tracking, mouse moves aren't reported.
+    assert_eq!(messages.mouse_move_stats(), None);
+    assert_eq!(messages.next(), None);
+}
+
+#[test]
+fn mouse_move_stats_compare_reported_and_drained_moves() {
+    let messages = [msg(WM_MOUSEMOVE, 1), msg(WM_USER, 2), msg(WM_MOUSEMOVE, 3)];
+
+    // The queue reports at most one new mouse move per batch, however many moves were coalesced or posted.
+    let mut reported = MessageIterator::from_source(ScriptedMessages::new(messages))
+        .with_reported_mouse_move(true);
+    assert_eq!(
+        reported.mouse_move_stats(),
+        Some(MouseMoveStats {
+            reported: 1,
+            drained: 0
+        })
+    );
+    assert_eq!(reported.by_ref().count(), 3);
+    assert_eq!(
+        reported.mouse_move_stats(),
+        Some(MouseMoveStats {
+            reported: 1,
+            drained: 2
+        })
+    );
+
+    let mut buffer = Vec::new();
+    let mut unreported = MessageIterator::from_source(ScriptedMessages::new(messages))
+        .with_reported_mouse_move(false);
+    assert_eq!(unreported.drain_into(&mut buffer).unwrap(), 3);
+    assert_eq!(
+        unreported.mouse_move_stats(),
+        Some(MouseMoveStats {
+            reported: 0,
+            drained: 2
+        })
+    );
+}
+
+#[test]
+fn typed_scripted_messages() {
+    let source = ScriptedMessages::new([msg(WM_USER, 7)]);
+
+    let messages = MessageIterator::from_source(source)
+        .typed()
+        .collect::<Vec<_>>();
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].id(), WM_USER);
+    assert_eq!(messages[0].wparam(), WPARAM(7));
+}
@@ -0,0 +1,109 @@
+mod helpers;
+
+use std::time::Duration;
+
+use async_messages::{PAINT_RECHECK_INTERVAL, next_message, wait_for_messages};
+use helpers::window::{create_window, register_window_class};
+use tokio::{runtime::Builder, time::timeout};
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    Graphics::Gdi::{InvalidateRect, ValidateRect},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{
+        MWMO_INPUTAVAILABLE, MWMO_NONE, PostThreadMessageW, QS_ALLINPUT, QS_PAINT, SW_SHOWNA,
+        ShowWindow, WM_PAINT, WM_USER,
+    },
+};
+
+#[test]
+fn unvalidated_paint_doesnt_spin() {
+    let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+
+    let window_class = register_window_class(None).unwrap();
+    let window = create_window(&window_class, None).unwrap();
+
+    unsafe {
+        // Showing the window leaves a WM_PAINT pending.
+        _ = ShowWindow(**window, SW_SHOWNA);
+    }
+
+    for wait_flags in [MWMO_NONE, MWMO_INPUTAVAILABLE] {
+        unsafe {
+            _ = ValidateRect(Some(**window), None);
+            _ = InvalidateRect(Some(**window), None, false);
+        }
+
+        runtime.block_on(async {
+            // Retrieving the WM_PAINT without painting leaves the update region invalid.
+            let drained = wait_for_messages(QS_PAINT, wait_flags)
+                .unwrap()
+                .await
+                .unwrap()
+                .map(|msg| msg.message)
+                .collect::<Vec<_>>();
+            assert_eq!(drained, [WM_PAINT]);
+
+            let mut resolved = 0;
+            let _ = timeout(Duration::from_millis(200), async {
+                loop {
+                    wait_for_messages(QS_PAINT, wait_flags)
+                        .unwrap()
+                        .await
+                        .unwrap()
+                        .for_each(drop);
+                    resolved += 1;
+                }
+            })
+            .await;
+            assert_eq!(resolved, 0);
+
+            unsafe {
+                _ = ValidateRect(Some(**window), None);
+                _ = InvalidateRect(Some(**window), None, false);
+            }
+
+            // The new paint is picked up by the next recheck.
+            let drained = timeout(
+                PAINT_RECHECK_INTERVAL * 10,
+                wait_for_messages(QS_PAINT, wait_flags).unwrap(),
+            )
+            .await
+            .unwrap()
+            .unwrap()
+            .map(|msg| msg.message)
+            .collect::<Vec<_>>();
+            assert_eq!(drained, [WM_PAINT]);
+        });
+    }
+}
+
+#[test]
+fn paint_behind_posted_message_is_reported() {
+    let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+
+    let window_class = register_window_class(None).unwrap();
+    let window = create_window(&window_class, None).unwrap();
+
+    unsafe {
+        _ = ShowWindow(**window, SW_SHOWNA);
+        _ = ValidateRect(Some(**window), None);
+        _ = InvalidateRect(Some(**window), None, false);
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+    }
+
+    runtime.block_on(async {
+        // Posted messages come before WM_PAINT, so only the post is retrieved. This clears the paint's "added" bit,
+        // even though it hasn't been yielded.
+        let msg = next_message(QS_ALLINPUT, MWMO_NONE).await.unwrap();
+        assert_eq!(msg.message, WM_USER);
+
+        let msg = timeout(
+            PAINT_RECHECK_INTERVAL * 10,
+            next_message(QS_ALLINPUT, MWMO_NONE),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(msg.message, WM_PAINT);
+    });
+}
@@ -0,0 +1,57 @@
+mod helpers;
+
+use async_messages::MessageWaiter;
+use helpers::window::{create_window, register_window_class};
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    UI::WindowsAndMessaging::{
+        MSG, MWMO_NONE, PM_NOREMOVE, PM_NOYIELD, PM_QS_PAINT, PM_QS_POSTMESSAGE, PeekMessageW,
+        PostMessageW, QS_ALLINPUT, SW_SHOWNA, ShowWindow, WM_PAINT, WM_USER,
+    },
+};
+
+#[test]
+fn drains_only_posted_messages() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    let window_class = register_window_class(None).unwrap();
+    let window = create_window(&window_class, None).unwrap();
+
+    unsafe {
+        // Showing the window leaves a WM_PAINT pending.
+        _ = ShowWindow(**window, SW_SHOWNA);
+        PostMessageW(Some(**window), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+    }
+
+    let mut waiter = MessageWaiter::builder(QS_ALLINPUT, MWMO_NONE)
+        .peek_qualifiers(PM_QS_POSTMESSAGE)
+        .build()
+        .unwrap();
+
+    let drained = runtime.block_on(async {
+        waiter
+            .wait()
+            .await
+            .unwrap()
+            .map(|msg| msg.message)
+            .collect::<Vec<_>>()
+    });
+
+    assert!(drained.contains(&WM_USER));
+    assert!(!drained.contains(&WM_PAINT));
+
+    let mut msg = MSG::default();
+    assert!(unsafe { PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE | PM_QS_PAINT) }.as_bool());
+    assert_eq!(msg.message, WM_PAINT);
+}
+
+#[test]
+fn rejects_non_qualifier_flags() {
+    assert!(
+        MessageWaiter::builder(QS_ALLINPUT, MWMO_NONE)
+            .peek_qualifiers(PM_NOYIELD)
+            .build()
+            .is_err()
+    );
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use async_messages::queue_status_stream;
+use tokio::{runtime::Builder, time::timeout};
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{
+        MSG, MWMO_NONE, PM_NOREMOVE, PM_REMOVE, PeekMessageW, PostThreadMessageW,
+        QS_ALLPOSTMESSAGE, QS_POSTMESSAGE, WM_USER,
+    },
+};
+
+#[test]
+fn reports_changes_without_draining() {
+    let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+
+    runtime.block_on(async {
+        let mut stream = queue_status_stream(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_min_interval(Duration::from_millis(5));
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+        }
+
+        let status = stream.next().await.unwrap();
+        assert_ne!(status.current().0 & QS_POSTMESSAGE.0, 0);
+
+        let mut msg = MSG::default();
+        assert!(unsafe { PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE) }.as_bool());
+
+        // The message is still queued, so the status doesn't change.
+        assert!(
+            timeout(Duration::from_millis(50), stream.next())
+                .await
+                .is_err()
+        );
+
+        // Once the queue has been drained, the stream waits for new messages.
+        assert!(unsafe { PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE) }.as_bool());
+        assert!(
+            timeout(Duration::from_millis(50), stream.next())
+                .await
+                .is_err()
+        );
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+        }
+
+        let status = timeout(Duration::from_secs(2), stream.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!status.is_empty());
+    });
+}
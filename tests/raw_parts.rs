@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use async_messages::{InputEventFuture, MessageWaiter};
+use nt_user_call::functions::{
+    NtUserCancelQueueEventCompletionPacket, NtUserClearWakeMask, NtUserGetInputEvent,
+    NtUserReassociateQueueEventCompletionPacket,
+};
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{E_INVALIDARG, HANDLE, LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER},
+};
+
+fn in_new_thread(f: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(f).join().unwrap();
+}
+
+#[test]
+fn waits_on_externally_configured_input_event() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        // Configure the input event like other message-waiting code would. The wait flags go into the high word.
+        let input_event = unsafe { NtUserGetInputEvent(QS_ALLPOSTMESSAGE.0) }
+            .map_err(windows::core::Error::from)
+            .unwrap();
+        unsafe {
+            _ = NtUserCancelQueueEventCompletionPacket();
+        }
+
+        let future = unsafe {
+            InputEventFuture::from_raw_parts(input_event, QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap()
+        };
+
+        let thread_id = unsafe { GetCurrentThreadId() };
+        let poster = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            unsafe { PostThreadMessageW(thread_id, WM_USER, WPARAM(0), LPARAM(0)).unwrap() };
+        });
+
+        assert_eq!(runtime.block_on(future).unwrap().count(), 1);
+        poster.join().unwrap();
+
+        unsafe {
+            NtUserClearWakeMask()
+                .map_err(windows::core::Error::from)
+                .unwrap();
+            _ = NtUserReassociateQueueEventCompletionPacket();
+        }
+    });
+}
+
+#[test]
+fn rejects_null_input_event() {
+    let error = unsafe {
+        InputEventFuture::from_raw_parts(HANDLE::default(), QS_ALLPOSTMESSAGE, MWMO_NONE)
+    }
+    .err()
+    .unwrap();
+    assert_eq!(error.code(), E_INVALIDARG);
+
+    let error = unsafe {
+        MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE).input_event(HANDLE::default())
+    }
+    .build()
+    .err()
+    .unwrap();
+    assert_eq!(error.code(), E_INVALIDARG);
+}
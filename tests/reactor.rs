@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use async_messages::{Backend, MessageWaiter, block_on_reactor, wait_for_messages};
+use windows::Win32::{
+    Foundation::{E_ILLEGAL_METHOD_CALL, LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER},
+};
+
+#[test]
+fn reactor_wakes_on_posted_message() {
+    let thread_id = unsafe { GetCurrentThreadId() };
+
+    let poster = std::thread::spawn(move || {
+        for i in 0..3 {
+            std::thread::sleep(Duration::from_millis(20));
+            unsafe { PostThreadMessageW(thread_id, WM_USER, WPARAM(i), LPARAM(0)) }.unwrap();
+        }
+    });
+
+    let received = block_on_reactor(async {
+        let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .backend(Backend::Reactor)
+            .build()
+            .unwrap();
+
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            received.extend(waiter.wait().await.unwrap().map(|msg| msg.wParam.0));
+        }
+
+        received
+    })
+    .unwrap();
+
+    poster.join().unwrap();
+    assert_eq!(received, [0, 1, 2]);
+}
+
+#[test]
+fn reactor_backend_requires_reactor() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    let error = runtime
+        .block_on(
+            wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+                .unwrap()
+                .with_backend(Backend::Reactor),
+        )
+        .err()
+        .unwrap();
+    assert_eq!(error.code(), E_ILLEGAL_METHOD_CALL);
+}
+
+#[test]
+fn reactor_rejects_second_pending_wait() {
+    let (first, second) = block_on_reactor(async {
+        let first = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_backend(Backend::Reactor);
+
+        let second = async {
+            let error = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+                .unwrap()
+                .with_backend(Backend::Reactor)
+                .await
+                .err()
+                .unwrap();
+
+            // Resolves the first wait, which must still be armed.
+            unsafe { PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)) }
+                .unwrap();
+
+            error
+        };
+
+        tokio::join!(first, second)
+    })
+    .unwrap();
+
+    assert_eq!(
+        first.unwrap().map(|msg| msg.message).collect::<Vec<_>>(),
+        [WM_USER]
+    );
+    assert_eq!(second.code(), E_ILLEGAL_METHOD_CALL);
+}
@@ -0,0 +1,164 @@
+mod helpers;
+
+use std::{
+    mem::MaybeUninit,
+    time::{Duration, Instant},
+};
+
+use async_messages::{MessageWaiter, process_sent_messages, recv_one_timeout};
+use helpers::window::{create_window, register_window_class};
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{E_INVALIDARG, HWND, LPARAM, LRESULT, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{
+        DefWindowProcW, GetQueueStatus, HWND_MESSAGE, MWMO_NONE, PM_REMOVE, PeekMessageW,
+        PostThreadMessageW, QS_ALLINPUT, QS_SENDMESSAGE, SMTO_NORMAL, SendMessageTimeoutW, WM_USER,
+    },
+};
+
+const WM_SENT: u32 = WM_USER + 1;
+const WM_POSTED: u32 = WM_USER + 2;
+
+unsafe extern "system" fn window_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_SENT {
+        return LRESULT(wparam.0 as isize * 2);
+    }
+
+    unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) }
+}
+
+fn in_new_thread(f: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(f).join().unwrap();
+}
+
+/// Sends `WM_SENT` to `hwnd` from another thread, returning the window procedure's result.
+fn send_from_other_thread(hwnd: HWND) -> std::thread::JoinHandle<usize> {
+    let hwnd = hwnd.0 as usize;
+
+    std::thread::spawn(move || {
+        let mut result = 0;
+        let sent = unsafe {
+            SendMessageTimeoutW(
+                HWND(hwnd as _),
+                WM_SENT,
+                WPARAM(21),
+                LPARAM(0),
+                SMTO_NORMAL,
+                5000,
+                Some(&mut result),
+            )
+        };
+        assert_ne!(sent.0, 0, "SendMessageTimeoutW failed or timed out");
+
+        result
+    })
+}
+
+/// Removes the next posted message and returns its wParam.
+fn next_posted() -> Option<usize> {
+    let mut msg = MaybeUninit::uninit();
+    unsafe { PeekMessageW(msg.as_mut_ptr(), None, 0, 0, PM_REMOVE).as_bool() }
+        .then(|| unsafe { msg.assume_init() }.wParam.0)
+}
+
+#[test]
+fn process_sent_messages_leaves_posted_queue_untouched() {
+    in_new_thread(|| {
+        let window_class = register_window_class(Some(window_proc)).unwrap();
+        let window = create_window(&window_class, Some(HWND_MESSAGE)).unwrap();
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_POSTED, WPARAM(1), LPARAM(0)).unwrap();
+            PostThreadMessageW(GetCurrentThreadId(), WM_POSTED, WPARAM(2), LPARAM(0)).unwrap();
+        }
+
+        let sender = send_from_other_thread(**window);
+
+        while unsafe { GetQueueStatus(QS_SENDMESSAGE) } >> 16 == 0 {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        process_sent_messages();
+
+        assert_eq!(sender.join().unwrap(), 42);
+        assert_eq!(next_posted(), Some(1));
+        assert_eq!(next_posted(), Some(2));
+        assert_eq!(next_posted(), None);
+    });
+}
+
+#[test]
+fn sent_messages_only_processes_sends() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let window_class = register_window_class(Some(window_proc)).unwrap();
+        let window = create_window(&window_class, Some(HWND_MESSAGE)).unwrap();
+
+        let mut waiter = MessageWaiter::builder(QS_SENDMESSAGE, MWMO_NONE)
+            .sent_messages_only(true)
+            .build()
+            .unwrap();
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_POSTED, WPARAM(1), LPARAM(0)).unwrap();
+        }
+
+        let sender = send_from_other_thread(**window);
+
+        runtime.block_on(async {
+            assert_eq!(waiter.wait().await.unwrap().count(), 0);
+        });
+
+        assert_eq!(sender.join().unwrap(), 42);
+        assert_eq!(next_posted(), Some(1));
+        assert_eq!(next_posted(), None);
+    });
+}
+
+#[test]
+fn recv_one_timeout_keeps_deadline_across_sent_messages() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let window_class = register_window_class(Some(window_proc)).unwrap();
+        let window = create_window(&window_class, Some(HWND_MESSAGE)).unwrap();
+
+        let hwnd = (**window).0 as usize;
+        let sender = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(150));
+            send_from_other_thread(HWND(hwnd as _)).join().unwrap()
+        });
+
+        // The sent message wakes the wait without leaving a message to return, so it's re-armed.
+        let start = Instant::now();
+        let msg = runtime
+            .block_on(recv_one_timeout(
+                QS_ALLINPUT,
+                MWMO_NONE,
+                Duration::from_millis(300),
+            ))
+            .unwrap();
+        let elapsed = start.elapsed();
+
+        assert_eq!(sender.join().unwrap(), 42);
+        assert!(msg.is_none());
+        assert!(elapsed >= Duration::from_millis(300));
+        // Re-arming with the full timeout would have taken at least 450 ms.
+        assert!(elapsed < Duration::from_millis(450), "{elapsed:?}");
+    });
+}
+
+#[test]
+fn sent_messages_only_requires_send_message_flag() {
+    let error = MessageWaiter::builder(QS_ALLINPUT, MWMO_NONE)
+        .sent_messages_only(true)
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(error.code(), E_INVALIDARG);
+}
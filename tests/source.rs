@@ -0,0 +1,58 @@
+#![cfg(feature = "tokio")]
+
+use async_messages::{QuitCode, spawn_message_source};
+use tokio::{runtime::Builder, task::LocalSet};
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{
+        MWMO_NONE, PostQuitMessage, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER,
+    },
+};
+
+fn in_new_thread(f: impl FnOnce() + Send + 'static) {
+    std::thread::spawn(f).join().unwrap();
+}
+
+#[test]
+fn forwards_messages_until_quit() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let local = LocalSet::new();
+
+        local.block_on(&runtime, async {
+            let (handle, mut receiver) =
+                spawn_message_source(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+            unsafe {
+                PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+                PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(2), LPARAM(0)).unwrap();
+            }
+
+            assert_eq!(receiver.recv().await.unwrap().wParam.0, 1);
+            assert_eq!(receiver.recv().await.unwrap().wParam.0, 2);
+
+            unsafe { PostQuitMessage(5) };
+
+            assert_eq!(handle.await.unwrap().unwrap(), Some(QuitCode(5)));
+            assert!(receiver.recv().await.is_none());
+        });
+    });
+}
+
+#[test]
+fn dropping_receiver_cancels_wait() {
+    in_new_thread(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let local = LocalSet::new();
+
+        local.block_on(&runtime, async {
+            let (handle, receiver) = spawn_message_source(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+            tokio::task::yield_now().await;
+            drop(receiver);
+
+            assert_eq!(handle.await.unwrap().unwrap(), None);
+        });
+    });
+}
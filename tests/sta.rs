@@ -0,0 +1,176 @@
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    mpsc,
+};
+
+use async_messages::{block_on_sta, wait_for_messages};
+use windows::{
+    Win32::{
+        Foundation::{LPARAM, WPARAM},
+        System::{
+            Com::{
+                COINIT_APARTMENTTHREADED, COINIT_MULTITHREADED, CoGetInterfaceAndReleaseStream,
+                CoInitializeEx, CoMarshalInterThreadInterfaceInStream, CoUninitialize, IPersist,
+                IPersist_Impl, IStream,
+            },
+            Threading::GetCurrentThreadId,
+        },
+        UI::WindowsAndMessaging::{
+            KillTimer, MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, QS_TIMER, SetTimer,
+            WM_TIMER, WM_USER,
+        },
+    },
+    core::{GUID, Interface, implement},
+};
+
+/// Records the thread `GetClassID` was called on.
+#[implement(IPersist)]
+struct Persist(&'static AtomicU32);
+
+impl IPersist_Impl for Persist_Impl {
+    fn GetClassID(&self) -> windows::core::Result<GUID> {
+        self.0
+            .store(unsafe { GetCurrentThreadId() }, Ordering::SeqCst);
+        Ok(GUID::zeroed())
+    }
+}
+
+struct SendStream(IStream);
+
+// SAFETY: The stream holds a marshaled interface, which is meant to be moved to another thread.
+unsafe impl Send for SendStream {}
+
+#[test]
+fn services_cross_apartment_calls() {
+    static CALLED_ON_THREAD: AtomicU32 = AtomicU32::new(0);
+
+    let (stream_tx, stream_rx) = mpsc::channel();
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+
+    let sta = std::thread::spawn(move || unsafe {
+        CoInitializeEx(None, COINIT_APARTMENTTHREADED).unwrap();
+
+        let persist: IPersist = Persist(&CALLED_ON_THREAD).into();
+        let stream = CoMarshalInterThreadInterfaceInStream(&IPersist::IID, &persist).unwrap();
+        stream_tx.send(SendStream(stream)).unwrap();
+
+        // Without pumping, the call from the other apartment would never be delivered.
+        block_on_sta(done_rx).unwrap().unwrap();
+
+        drop(persist);
+        CoUninitialize();
+
+        GetCurrentThreadId()
+    });
+
+    std::thread::spawn(move || unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).unwrap();
+
+        let SendStream(stream) = stream_rx.recv().unwrap();
+        let persist: IPersist = CoGetInterfaceAndReleaseStream(&stream).unwrap();
+        persist.GetClassID().unwrap();
+
+        drop(persist);
+        CoUninitialize();
+
+        done_tx.send(()).unwrap();
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(sta.join().unwrap(), CALLED_ON_THREAD.load(Ordering::SeqCst));
+}
+
+#[test]
+fn waits_for_messages_across_cross_apartment_calls() {
+    static CALLED_ON_THREAD: AtomicU32 = AtomicU32::new(0);
+
+    let (stream_tx, stream_rx) = mpsc::channel();
+
+    let sta = std::thread::spawn(move || unsafe {
+        CoInitializeEx(None, COINIT_APARTMENTTHREADED).unwrap();
+
+        let persist: IPersist = Persist(&CALLED_ON_THREAD).into();
+        let stream = CoMarshalInterThreadInterfaceInStream(&IPersist::IID, &persist).unwrap();
+        stream_tx
+            .send((GetCurrentThreadId(), SendStream(stream)))
+            .unwrap();
+
+        // The modal loop dispatching the call clears the wake mask the wait was armed with. COM posts messages of its
+        // own to the thread, so only WM_USER is drained.
+        let messages = block_on_sta(async {
+            wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+                .unwrap()
+                .with_message_filter(WM_USER..=WM_USER)
+                .await
+                .unwrap()
+                .map(|msg| msg.wParam.0)
+                .collect::<Vec<_>>()
+        })
+        .unwrap();
+
+        drop(persist);
+        CoUninitialize();
+
+        messages
+    });
+
+    let thread_id = std::thread::spawn(move || unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).unwrap();
+
+        let (thread_id, SendStream(stream)) = stream_rx.recv().unwrap();
+        let persist: IPersist = CoGetInterfaceAndReleaseStream(&stream).unwrap();
+        persist.GetClassID().unwrap();
+
+        drop(persist);
+        CoUninitialize();
+
+        PostThreadMessageW(thread_id, WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+
+        thread_id
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(sta.join().unwrap(), [1]);
+    assert_eq!(CALLED_ON_THREAD.load(Ordering::SeqCst), thread_id);
+}
+
+#[test]
+fn waits_for_messages_without_cross_apartment_calls() {
+    std::thread::spawn(|| unsafe {
+        CoInitializeEx(None, COINIT_APARTMENTTHREADED).unwrap();
+
+        // The modal loop doesn't wait for timer messages, so only the executor's own wait picks this up.
+        let timer = SetTimer(None, 0, 50, None);
+        assert_ne!(timer, 0);
+
+        let messages = block_on_sta(async {
+            wait_for_messages(QS_TIMER, MWMO_NONE)
+                .unwrap()
+                .await
+                .unwrap()
+                .map(|msg| msg.message)
+                .collect::<Vec<_>>()
+        })
+        .unwrap();
+
+        KillTimer(None, timer).unwrap();
+        CoUninitialize();
+
+        assert!(messages.contains(&WM_TIMER));
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn rejects_multithreaded_apartment() {
+    std::thread::spawn(|| unsafe {
+        CoInitializeEx(None, COINIT_MULTITHREADED).unwrap();
+        assert!(block_on_sta(async {}).is_err());
+        CoUninitialize();
+    })
+    .join()
+    .unwrap();
+}
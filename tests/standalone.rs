@@ -1,13 +1,15 @@
 use std::{
     future::Future,
     pin::Pin,
+    sync::mpsc,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use async_messages::*;
 use windows::{
     Win32::{
-        Foundation::{LPARAM, WAIT_OBJECT_0, WPARAM},
+        Foundation::{E_ILLEGAL_METHOD_CALL, LPARAM, WAIT_OBJECT_0, WPARAM},
         System::Threading::{CreateEventW, GetCurrentThreadId, WaitForSingleObject},
         UI::WindowsAndMessaging::{
             MSG, MWMO_NONE, PM_NOREMOVE, PeekMessageW, PostThreadMessageW, QS_ALLPOSTMESSAGE,
@@ -104,7 +106,7 @@ pub fn thread_messages() {
         let mut context = Context::from_waker(&waker);
 
         assert!(matches!(
-            Pin::new_unchecked(&mut future).poll(&mut context),
+            Pin::new(&mut future).poll(&mut context),
             Poll::Pending
         ));
 
@@ -113,3 +115,379 @@ pub fn thread_messages() {
         assert_eq!(WaitForSingleObject(*event, 2000), WAIT_OBJECT_0);
     });
 }
+
+#[test]
+pub fn threadpool_callback_resolves_armed_wait() {
+    in_new_thread(|| unsafe {
+        let mut msg = MSG::default();
+        assert!(!PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE).as_bool());
+
+        let mut future = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+        let event = Owned::new(CreateEventW(None, true, false, None).unwrap());
+        let waker = handle_waker::handle_waker(*event).unwrap();
+        let mut context = Context::from_waker(&waker);
+
+        // The queue is empty, so this arms the threadpool wait instead of taking the fast path.
+        assert!(matches!(
+            Pin::new(&mut future).poll(&mut context),
+            Poll::Pending
+        ));
+        assert!(future.explain_wait_state().wait_armed);
+
+        let thread_id = GetCurrentThreadId();
+        let poster = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            PostThreadMessageW(thread_id, WM_USER, WPARAM(42), LPARAM(0)).unwrap();
+        });
+
+        assert_eq!(WaitForSingleObject(*event, 2000), WAIT_OBJECT_0);
+        poster.join().unwrap();
+        assert_eq!(future.explain_wait_state().state, WaitState::Ready);
+
+        let Poll::Ready(Ok(messages)) = Pin::new(&mut future).poll(&mut context) else {
+            panic!("expected the woken future to be ready");
+        };
+        assert_eq!(
+            messages
+                .map(|msg| (msg.message, msg.wParam.0))
+                .collect::<Vec<_>>(),
+            [(WM_USER, 42)]
+        );
+        assert!(!future.explain_wait_state().wait_armed);
+        assert!(!PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE).as_bool());
+    });
+}
+
+#[test]
+pub fn pending_future_can_be_moved() {
+    in_new_thread(|| unsafe {
+        let mut future = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+        let event = Owned::new(CreateEventW(None, true, false, None).unwrap());
+        let waker = handle_waker::handle_waker(*event).unwrap();
+        let mut context = Context::from_waker(&waker);
+
+        assert!(matches!(
+            Pin::new(&mut future).poll(&mut context),
+            Poll::Pending
+        ));
+
+        // The callback's context lives on the heap, so moving the armed future doesn't invalidate it.
+        let mut moved = Box::new(future);
+
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+
+        assert_eq!(WaitForSingleObject(*event, 2000), WAIT_OBJECT_0);
+        let Poll::Ready(Ok(messages)) = Pin::new(&mut *moved).poll(&mut context) else {
+            panic!("expected the moved future to be ready");
+        };
+        assert_eq!(messages.count(), 1);
+    });
+}
+
+#[test]
+pub fn detached_wait_completes() {
+    in_new_thread(|| unsafe {
+        let (tx, rx) = mpsc::channel();
+
+        wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .detach(move || tx.send(()).unwrap());
+
+        assert!(rx.try_recv().is_err());
+
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+
+        rx.recv_timeout(Duration::from_secs(2)).unwrap();
+
+        let mut msg = MSG::default();
+        assert!(PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE).as_bool());
+        assert_eq!(msg.message, WM_USER);
+    });
+}
+
+#[test]
+pub fn detached_wait_drains_on_reap() {
+    in_new_thread(|| unsafe {
+        let (tx, rx) = mpsc::channel();
+        let (messages_tx, messages_rx) = mpsc::channel();
+
+        wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .detach_draining(
+                move || tx.send(()).unwrap(),
+                move |messages| messages.for_each(|msg| messages_tx.send(msg.wParam.0).unwrap()),
+            );
+
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(7), LPARAM(0)).unwrap();
+        rx.recv_timeout(Duration::from_secs(2)).unwrap();
+
+        // Nothing is drained until the completed wait is released on this thread.
+        assert!(messages_rx.try_recv().is_err());
+        reap_detached();
+        assert_eq!(messages_rx.try_recv(), Ok(7));
+
+        // The wait no longer counts as armed.
+        let mut future = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_overlap_policy(OverlapPolicy::Error);
+        let waker = std::task::Waker::noop();
+        assert!(
+            Pin::new(&mut future)
+                .poll(&mut Context::from_waker(waker))
+                .is_pending()
+        );
+    });
+}
+
+#[test]
+pub fn filtered_detached_wait_notifies_on_matching_message() {
+    in_new_thread(|| unsafe {
+        let (tx, rx) = mpsc::channel();
+        let (messages_tx, messages_rx) = mpsc::channel();
+
+        wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_message_filter(WM_USER..=WM_USER)
+            .detach_draining(
+                move || tx.send(()).unwrap(),
+                move |messages| messages.for_each(|msg| messages_tx.send(msg.message).unwrap()),
+            );
+
+        // The wait is woken up by a message outside the filter, which reaping finds and arms the wait again for.
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER + 1, WPARAM(0), LPARAM(0)).unwrap();
+        rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        reap_detached();
+        assert!(messages_rx.try_recv().is_err());
+
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+        rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        reap_detached();
+        assert_eq!(messages_rx.try_recv(), Ok(WM_USER));
+
+        // The message outside the filter is still queued.
+        let mut msg = MSG::default();
+        assert!(PeekMessageW(&mut msg, None, 0, 0, PM_NOREMOVE).as_bool());
+        assert_eq!(msg.message, WM_USER + 1);
+    });
+}
+
+#[test]
+pub fn wake_hook_runs_on_threadpool_thread() {
+    in_new_thread(|| unsafe {
+        let (tx, rx) = mpsc::channel();
+
+        let mut future = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_wake_hook(move || tx.send(GetCurrentThreadId()).unwrap());
+
+        let event = Owned::new(CreateEventW(None, true, false, None).unwrap());
+        let waker = handle_waker::handle_waker(*event).unwrap();
+        let mut context = Context::from_waker(&waker);
+
+        assert!(matches!(
+            Pin::new(&mut future).poll(&mut context),
+            Poll::Pending
+        ));
+
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+
+        let hook_thread_id = rx.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_ne!(hook_thread_id, GetCurrentThreadId());
+        assert_eq!(WaitForSingleObject(*event, 2000), WAIT_OBJECT_0);
+    });
+}
+
+#[test]
+pub fn input_event_is_shared_thread_resource() {
+    // Arming a wait asserts (in debug builds) that the thread's input event is the same handle every time.
+    in_new_thread(|| unsafe {
+        let event = Owned::new(CreateEventW(None, true, false, None).unwrap());
+        let waker = handle_waker::handle_waker(*event).unwrap();
+        let mut context = Context::from_waker(&waker);
+
+        for _ in 0..3 {
+            let mut future = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+            assert!(matches!(
+                Pin::new(&mut future).poll(&mut context),
+                Poll::Pending
+            ));
+        }
+    });
+}
+
+#[test]
+pub fn explain_wait_state() {
+    in_new_thread(|| unsafe {
+        let mut future = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+        let snapshot = future.explain_wait_state();
+        assert_eq!(snapshot.state, WaitState::NotPending);
+        assert!(!snapshot.wait_armed);
+        assert!(!snapshot.waker_registered);
+        assert_eq!(snapshot.last_queue_status, None);
+
+        let event = Owned::new(CreateEventW(None, true, false, None).unwrap());
+        let waker = handle_waker::handle_waker(*event).unwrap();
+        let mut context = Context::from_waker(&waker);
+
+        let mut future = Pin::new(&mut future);
+        assert!(matches!(future.as_mut().poll(&mut context), Poll::Pending));
+
+        let snapshot = future.explain_wait_state();
+        assert_eq!(snapshot.state, WaitState::Pending);
+        assert_eq!(snapshot.queue_status_flags, QS_ALLPOSTMESSAGE);
+        assert!(snapshot.wait_armed);
+        assert!(snapshot.waker_registered);
+        assert_eq!(snapshot.last_queue_status, Some(0));
+        assert!(snapshot.to_string().starts_with("state: Pending,"));
+    });
+}
+
+#[test]
+pub fn drop_does_not_block_on_wedged_callback() {
+    in_new_thread(|| unsafe {
+        let (tx, rx) = mpsc::channel();
+
+        let mut future = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_callback_timeout(Duration::from_millis(50))
+            .with_wake_hook(move || {
+                tx.send(()).unwrap();
+                std::thread::sleep(Duration::from_secs(2));
+            });
+
+        let event = Owned::new(CreateEventW(None, true, false, None).unwrap());
+        let waker = handle_waker::handle_waker(*event).unwrap();
+        let mut context = Context::from_waker(&waker);
+
+        assert!(matches!(
+            Pin::new(&mut future).poll(&mut context),
+            Poll::Pending
+        ));
+
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+        rx.recv_timeout(Duration::from_secs(2)).unwrap();
+
+        let leaked_before = leaked_waits();
+        let start = std::time::Instant::now();
+        drop(future);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(leaked_waits(), leaked_before + 1);
+
+        // The leaked wait no longer counts as armed.
+        let mut future = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_overlap_policy(OverlapPolicy::Error);
+        assert!(!matches!(
+            Pin::new(&mut future).poll(&mut context),
+            Poll::Ready(Err(_))
+        ));
+    });
+}
+
+#[test]
+pub fn try_or_wait_returns_queued_messages() {
+    in_new_thread(|| unsafe {
+        let TryOrWait::Wait(future) = try_or_wait(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap() else {
+            panic!("expected an empty queue");
+        };
+        assert_eq!(future.explain_wait_state().state, WaitState::NotPending);
+
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+
+        let TryOrWait::Ready(messages) = try_or_wait(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap() else {
+            panic!("expected queued messages");
+        };
+        assert_eq!(
+            messages.map(|msg| msg.message).collect::<Vec<_>>(),
+            [WM_USER]
+        );
+    });
+}
+
+#[test]
+pub fn wait_after_clearing_wake_mask() {
+    in_new_thread(|| unsafe {
+        set_wake_mask(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+        clear_wake_mask().unwrap();
+
+        let mut future = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+        let event = Owned::new(CreateEventW(None, true, false, None).unwrap());
+        let waker = handle_waker::handle_waker(*event).unwrap();
+        let mut context = Context::from_waker(&waker);
+
+        assert!(matches!(
+            Pin::new(&mut future).poll(&mut context),
+            Poll::Pending
+        ));
+
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+
+        assert_eq!(WaitForSingleObject(*event, 2000), WAIT_OBJECT_0);
+    });
+}
+
+#[test]
+pub fn wait_without_completion_packet_management() {
+    in_new_thread(|| unsafe {
+        let (tx, rx) = mpsc::channel();
+
+        // No completion port is associated with this thread's queue, so the wait completes without cancelling the
+        // packet.
+        wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_completion_packet_management(false)
+            .detach(move || tx.send(()).unwrap());
+
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+
+        rx.recv_timeout(Duration::from_secs(2)).unwrap();
+    });
+}
+
+#[test]
+pub fn overlap_policy_rejects_second_wait() {
+    in_new_thread(|| unsafe {
+        let event = Owned::new(CreateEventW(None, true, false, None).unwrap());
+        let waker = handle_waker::handle_waker(*event).unwrap();
+        let mut context = Context::from_waker(&waker);
+
+        let mut first = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+        assert!(matches!(
+            Pin::new(&mut first).poll(&mut context),
+            Poll::Pending
+        ));
+
+        let mut second = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_overlap_policy(OverlapPolicy::Error);
+        let Poll::Ready(Err(error)) = Pin::new(&mut second).poll(&mut context) else {
+            panic!("expected the overlapping wait to fail");
+        };
+        assert_eq!(error.code(), E_ILLEGAL_METHOD_CALL);
+
+        let mut third = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_overlap_policy(OverlapPolicy::Panic);
+        assert!(
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                _ = Pin::new(&mut third).poll(&mut context);
+            }))
+            .is_err()
+        );
+
+        // Once the first wait is gone, guarded waits can be armed again.
+        drop(first);
+        let mut fourth = wait_for_messages(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .unwrap()
+            .with_overlap_policy(OverlapPolicy::Error);
+        assert!(matches!(
+            Pin::new(&mut fourth).poll(&mut context),
+            Poll::Pending
+        ));
+    });
+}
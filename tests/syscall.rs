@@ -0,0 +1,22 @@
+use async_messages::{Syscall, wait_for_messages};
+use windows::Win32::{
+    Foundation::E_INVALIDARG,
+    UI::WindowsAndMessaging::{MWMO_NONE, QUEUE_STATUS_FLAGS},
+};
+
+#[test]
+fn rejected_flags_are_not_attributed() {
+    let error = wait_for_messages(QUEUE_STATUS_FLAGS(0x10000), MWMO_NONE)
+        .err()
+        .unwrap();
+    assert_eq!(Syscall::of(&error), None);
+}
+
+#[test]
+fn attribution_is_not_parsed_from_message() {
+    let error = windows::core::Error::new(
+        E_INVALIDARG,
+        "failed in NtUserGetInputEvent: The parameter is incorrect.",
+    );
+    assert_eq!(Syscall::of(&error), None);
+}
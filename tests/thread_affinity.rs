@@ -0,0 +1,72 @@
+//! Compile-time checks for the thread affinity of the crate's types.
+//!
+//! Messages belong to the thread whose queue they were posted to, so neither the futures nor the iterators may be
+//! moved to or shared with another thread. If any of these types ever becomes `Send` or `Sync`, this file stops
+//! compiling. The cases in `tests/ui` additionally check that the compiler rejects sending them to another thread.
+
+use std::future::Future;
+
+use async_messages::{InputEventFuture, MessageIterator, MessageWaiter};
+use tokio::{runtime::Builder, task::LocalSet};
+use windows::Win32::{
+    Foundation::{LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{MWMO_NONE, PostThreadMessageW, QS_ALLPOSTMESSAGE, WM_USER},
+};
+
+/// Fails to compile if `$ty` implements `$trait`, as the call to `some_item` becomes ambiguous.
+macro_rules! assert_not_impl {
+    ($ty:ty: $trait:path) => {
+        const _: fn() = || {
+            trait AmbiguousIfImpl<A> {
+                fn some_item() {}
+            }
+
+            impl<T: ?Sized> AmbiguousIfImpl<()> for T {}
+
+            struct Invalid;
+            impl<T: ?Sized + $trait> AmbiguousIfImpl<Invalid> for T {}
+
+            let _ = <$ty as AmbiguousIfImpl<_>>::some_item;
+        };
+    };
+}
+
+assert_not_impl!(InputEventFuture: Send);
+assert_not_impl!(InputEventFuture: Sync);
+assert_not_impl!(MessageIterator<'static>: Send);
+assert_not_impl!(MessageIterator<'static>: Sync);
+assert_not_impl!(MessageWaiter: Send);
+assert_not_impl!(MessageWaiter: Sync);
+
+#[test]
+fn rejects_sending_to_another_thread() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
+
+/// The futures only need to be `'static` to be spawned on single-threaded executors such as tokio's `LocalSet`.
+fn assert_local_future<F: Future + 'static>() {}
+
+#[test]
+fn usable_on_single_threaded_executors() {
+    assert_local_future::<InputEventFuture>();
+
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let local = LocalSet::new();
+
+    let count = local.block_on(&runtime, async {
+        tokio::task::spawn_local(async {
+            let mut waiter = MessageWaiter::new(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+            unsafe {
+                PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+            }
+
+            waiter.wait().await.unwrap().count()
+        })
+        .await
+        .unwrap()
+    });
+
+    assert_eq!(count, 1);
+}
@@ -0,0 +1,7 @@
+use async_messages::InputEventFuture;
+
+fn require_send<T: Send>() {}
+
+fn main() {
+    require_send::<InputEventFuture>();
+}
@@ -0,0 +1,7 @@
+use async_messages::MessageIterator;
+
+fn require_send<T: Send>() {}
+
+fn main() {
+    require_send::<MessageIterator<'static>>();
+}
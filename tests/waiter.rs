@@ -0,0 +1,544 @@
+use std::{
+    future::poll_fn,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use async_messages::{
+    MaskChangePolicy, MessageSignal, MessageWaiter, MouseMoveStats, next_message, recv_one_timeout,
+};
+use tokio::runtime::Builder;
+use windows::Win32::{
+    Foundation::{E_ILLEGAL_METHOD_CALL, E_INVALIDARG, LPARAM, WPARAM},
+    System::Threading::GetCurrentThreadId,
+    UI::WindowsAndMessaging::{
+        MSG, MWMO_INPUTAVAILABLE, MWMO_NONE, PM_REMOVE, PeekMessageW, PostQuitMessage,
+        PostThreadMessageW, QS_ALLPOSTMESSAGE, QS_POSTMESSAGE, QUEUE_STATUS_FLAGS, WM_MOUSEMOVE,
+        WM_QUIT, WM_USER,
+    },
+};
+
+#[test]
+fn poll_next_batch_in_select() {
+    let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+
+    runtime.block_on(async {
+        let mut waiter = MessageWaiter::new(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+        let mut received = Vec::new();
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+        }
+
+        while received.len() < 2 {
+            tokio::select! {
+                messages = poll_fn(|cx| {
+                    waiter
+                        .poll_next_batch(cx)
+                        .map_ok(|messages| messages.map(|msg| msg.wParam.0).collect::<Vec<_>>())
+                }) => {
+                    received.extend(messages.unwrap());
+                }
+                _ = tokio::time::sleep(Duration::from_millis(50)) => unsafe {
+                    PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(2), LPARAM(0)).unwrap();
+                }
+            }
+        }
+
+        assert_eq!(received, [1, 2]);
+    });
+}
+
+#[test]
+fn message_filter_leaves_out_of_range_messages_queued() {
+    std::thread::spawn(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        runtime.block_on(async {
+            let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+                .message_filter(WM_USER..=WM_USER)
+                .build()
+                .unwrap();
+
+            unsafe {
+                PostThreadMessageW(GetCurrentThreadId(), WM_USER + 1, WPARAM(0), LPARAM(0))
+                    .unwrap();
+                PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+            }
+
+            let drained = waiter
+                .wait()
+                .await
+                .unwrap()
+                .map(|msg| msg.message)
+                .collect::<Vec<_>>();
+            assert_eq!(drained, [WM_USER]);
+
+            let mut msg = MSG::default();
+            assert!(unsafe { PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE) }.as_bool());
+            assert_eq!(msg.message, WM_USER + 1);
+        });
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn message_filter_with_input_available_does_not_spin() {
+    std::thread::spawn(|| {
+        let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+        let wakes = Arc::new(AtomicUsize::new(0));
+
+        runtime.block_on(async {
+            let mut waiter = unsafe {
+                let wakes = wakes.clone();
+                MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_INPUTAVAILABLE)
+                    .message_filter(WM_USER..=WM_USER)
+                    .wake_hook(move || _ = wakes.fetch_add(1, Ordering::Relaxed))
+            }
+            .build()
+            .unwrap();
+
+            unsafe {
+                PostThreadMessageW(GetCurrentThreadId(), WM_USER + 1, WPARAM(0), LPARAM(0))
+                    .unwrap();
+            }
+
+            // The queued message satisfies MWMO_INPUTAVAILABLE, so only the first wait may wake up for it.
+            let timed_out = tokio::time::timeout(Duration::from_millis(100), waiter.wait()).await;
+            assert!(timed_out.is_err());
+            assert!(wakes.load(Ordering::Relaxed) <= 1);
+
+            unsafe {
+                PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+            }
+
+            let drained = waiter
+                .wait()
+                .await
+                .unwrap()
+                .map(|msg| msg.message)
+                .collect::<Vec<_>>();
+            assert_eq!(drained, [WM_USER]);
+        });
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn next_message_leaves_remaining_messages_queued() {
+    std::thread::spawn(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(2), LPARAM(0)).unwrap();
+        }
+
+        let msg = runtime
+            .block_on(next_message(QS_ALLPOSTMESSAGE, MWMO_NONE))
+            .unwrap();
+        assert_eq!(msg.wParam.0, 1);
+
+        let msg = runtime
+            .block_on(next_message(QS_ALLPOSTMESSAGE, MWMO_NONE))
+            .unwrap();
+        assert_eq!(msg.wParam.0, 2);
+
+        let mut msg = MSG::default();
+        assert!(!unsafe { PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE) }.as_bool());
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn next_message_on_eager_waiter_leaves_remaining_messages_queued() {
+    std::thread::spawn(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .eager_drain(true)
+            .build()
+            .unwrap();
+
+        unsafe {
+            for i in 1..=3 {
+                PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(i), LPARAM(0)).unwrap();
+            }
+        }
+
+        runtime.block_on(async {
+            for i in 1..=3 {
+                assert_eq!(waiter.next_message().await.unwrap().wParam.0, i);
+            }
+        });
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn wait_snapshot_reuses_buffer() {
+    std::thread::spawn(|| {
+        let runtime = Builder::new_current_thread().build().unwrap();
+
+        runtime.block_on(async {
+            let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+                .buffer_capacity(16)
+                .build()
+                .unwrap();
+
+            let mut buffers = Vec::new();
+            for batch in 0..2 {
+                unsafe {
+                    for i in 0..4 {
+                        PostThreadMessageW(
+                            GetCurrentThreadId(),
+                            WM_USER,
+                            WPARAM(batch * 4 + i),
+                            LPARAM(0),
+                        )
+                        .unwrap();
+                    }
+                }
+
+                let snapshot = waiter.wait_snapshot().await.unwrap();
+                assert_eq!(
+                    snapshot.iter().map(|msg| msg.wParam.0).collect::<Vec<_>>(),
+                    (batch * 4..batch * 4 + 4).collect::<Vec<_>>()
+                );
+                buffers.push(snapshot.as_ptr());
+            }
+
+            assert_eq!(buffers[0], buffers[1]);
+        });
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn typed_messages() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    unsafe {
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(2)).unwrap();
+        PostQuitMessage(0);
+    }
+
+    let mut waiter = MessageWaiter::new(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+    let messages =
+        runtime.block_on(async { waiter.wait().await.unwrap().typed().collect::<Vec<_>>() });
+
+    assert_eq!(messages.len(), 2);
+
+    assert_eq!(messages[0].id(), WM_USER);
+    assert!(!messages[0].is_quit());
+    assert_eq!(messages[0].hwnd(), None);
+    assert_eq!(messages[0].wparam(), WPARAM(1));
+    assert_eq!(messages[0].lparam(), LPARAM(2));
+    assert_eq!(messages[0].as_raw().message, WM_USER);
+
+    assert_eq!(messages[1].id(), WM_QUIT);
+    assert!(messages[1].is_quit());
+}
+
+#[test]
+fn signal_resolves_all_subscriptions() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    let signal = MessageSignal::new(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+    let first = signal.subscribe();
+    let second = first.clone();
+    let third = signal.subscribe();
+
+    unsafe {
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+    }
+
+    let statuses = runtime.block_on(async {
+        let (first, second, third) = tokio::join!(first, second, third);
+        [first.unwrap(), second.unwrap(), third.unwrap()]
+    });
+
+    assert!(!statuses[0].is_empty());
+    assert!(statuses.iter().all(|status| *status == statuses[0]));
+
+    // The signal leaves the message queued for the consumer.
+    let mut msg = MSG::default();
+    assert!(unsafe { PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE) }.as_bool());
+    assert_eq!(msg.message, WM_USER);
+}
+
+#[test]
+fn eager_drain_captures_messages_at_resolution() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+        .eager_drain(true)
+        .build()
+        .unwrap();
+
+    unsafe {
+        PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+    }
+
+    runtime.block_on(async {
+        let messages = waiter.wait().await.unwrap();
+
+        // Posted after the wait resolved, so not part of this batch.
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(2), LPARAM(0)).unwrap();
+        }
+
+        assert_eq!(messages.map(|msg| msg.wParam.0).collect::<Vec<_>>(), [1]);
+
+        let messages = waiter.wait().await.unwrap();
+        assert_eq!(messages.map(|msg| msg.wParam.0).collect::<Vec<_>>(), [2]);
+    });
+}
+
+#[test]
+fn drain_into_appends_to_buffer() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    let mut waiter = MessageWaiter::new(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+    let mut buffer = vec![MSG::default()];
+
+    unsafe {
+        for i in 1..=3 {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(i), LPARAM(0)).unwrap();
+        }
+    }
+
+    let drained = runtime.block_on(waiter.drain_into(&mut buffer)).unwrap();
+
+    assert_eq!(drained, 3);
+    assert_eq!(
+        buffer.iter().map(|msg| msg.wParam.0).collect::<Vec<_>>(),
+        [0, 1, 2, 3]
+    );
+}
+
+#[test]
+fn spin_budget_defers_arming_the_wait() {
+    let runtime = Builder::new_current_thread().enable_time().build().unwrap();
+
+    let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+        .spin_budget(Some(Duration::from_millis(50)))
+        .build()
+        .unwrap();
+
+    runtime.block_on(async {
+        poll_fn(|cx| {
+            assert!(waiter.poll_next_batch(cx).is_pending());
+            Poll::Ready(())
+        })
+        .await;
+        assert!(!waiter.explain_wait_state().wait_armed);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        poll_fn(|cx| {
+            assert!(waiter.poll_next_batch(cx).is_pending());
+            Poll::Ready(())
+        })
+        .await;
+        assert!(waiter.explain_wait_state().wait_armed);
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+        }
+
+        assert_eq!(waiter.wait().await.unwrap().count(), 1);
+    });
+}
+
+#[test]
+fn mouse_move_stats_count_drained_moves() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    for eager in [false, true] {
+        let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+            .track_mouse_moves(true)
+            .eager_drain(eager)
+            .build()
+            .unwrap();
+
+        // Posted mouse moves are ordinary posted messages, so the queue never reports them as mouse moves.
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_MOUSEMOVE, WPARAM(0), LPARAM(0)).unwrap();
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(0), LPARAM(0)).unwrap();
+            PostThreadMessageW(GetCurrentThreadId(), WM_MOUSEMOVE, WPARAM(0), LPARAM(0)).unwrap();
+        }
+
+        runtime.block_on(async {
+            let mut messages = waiter.wait().await.unwrap();
+            assert_eq!(messages.by_ref().count(), 3);
+            assert_eq!(
+                messages.mouse_move_stats(),
+                Some(MouseMoveStats {
+                    reported: 0,
+                    drained: 2
+                })
+            );
+        });
+    }
+
+    let mut waiter = MessageWaiter::new(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+    unsafe {
+        PostThreadMessageW(GetCurrentThreadId(), WM_MOUSEMOVE, WPARAM(0), LPARAM(0)).unwrap();
+    }
+
+    runtime.block_on(async {
+        let messages = waiter.wait().await.unwrap();
+        assert_eq!(messages.mouse_move_stats(), None);
+        assert_eq!(messages.count(), 1);
+    });
+}
+
+#[test]
+fn recv_one_timeout_expires_without_messages() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    let start = Instant::now();
+    let msg = runtime
+        .block_on(recv_one_timeout(
+            QS_ALLPOSTMESSAGE,
+            MWMO_NONE,
+            Duration::from_millis(100),
+        ))
+        .unwrap();
+
+    assert!(msg.is_none());
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[test]
+fn recv_one_timeout_returns_message_arriving_before_deadline() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let thread_id = unsafe { GetCurrentThreadId() };
+
+    let poster = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(30));
+        unsafe {
+            PostThreadMessageW(thread_id, WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+            PostThreadMessageW(thread_id, WM_USER, WPARAM(2), LPARAM(0)).unwrap();
+        }
+    });
+
+    let msg = runtime
+        .block_on(recv_one_timeout(
+            QS_ALLPOSTMESSAGE,
+            MWMO_NONE,
+            Duration::from_secs(5),
+        ))
+        .unwrap()
+        .unwrap();
+    poster.join().unwrap();
+
+    assert_eq!(msg.message, WM_USER);
+    assert_eq!(msg.wParam.0, 1);
+
+    // Only the first message has been removed.
+    let mut msg = MSG::default();
+    assert!(unsafe { PeekMessageW(&mut msg, None, 0, 0, PM_REMOVE) }.as_bool());
+    assert_eq!(msg.wParam.0, 2);
+}
+
+#[test]
+fn set_mask_while_pending_is_deferred() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let mut waiter = MessageWaiter::new(QS_ALLPOSTMESSAGE, MWMO_NONE).unwrap();
+
+    runtime.block_on(async {
+        poll_fn(|cx| {
+            assert!(waiter.poll_next_batch(cx).is_pending());
+            Poll::Ready(())
+        })
+        .await;
+
+        // The pending wait keeps the mask it was armed with.
+        waiter
+            .set_mask(QS_POSTMESSAGE, MWMO_INPUTAVAILABLE)
+            .unwrap();
+        let snapshot = waiter.explain_wait_state();
+        assert_eq!(snapshot.queue_status_flags, QS_ALLPOSTMESSAGE);
+        assert_eq!(snapshot.wait_flags, MWMO_NONE);
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+        }
+        assert_eq!(waiter.wait().await.unwrap().count(), 1);
+
+        // The next wait is armed with the new mask.
+        poll_fn(|cx| {
+            assert!(waiter.poll_next_batch(cx).is_pending());
+            Poll::Ready(())
+        })
+        .await;
+        let snapshot = waiter.explain_wait_state();
+        assert!(snapshot.wait_armed);
+        assert_eq!(snapshot.queue_status_flags, QS_POSTMESSAGE);
+        assert_eq!(snapshot.wait_flags, MWMO_INPUTAVAILABLE);
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(2), LPARAM(0)).unwrap();
+        }
+        assert_eq!(waiter.wait().await.unwrap().count(), 1);
+    });
+}
+
+#[test]
+fn set_mask_while_pending_is_rejected() {
+    let runtime = Builder::new_current_thread().build().unwrap();
+    let mut waiter = MessageWaiter::builder(QS_ALLPOSTMESSAGE, MWMO_NONE)
+        .mask_change_policy(MaskChangePolicy::Reject)
+        .build()
+        .unwrap();
+
+    let error = waiter
+        .set_mask(QUEUE_STATUS_FLAGS(0x10000), MWMO_NONE)
+        .err()
+        .unwrap();
+    assert_eq!(error.code(), E_INVALIDARG);
+
+    runtime.block_on(async {
+        poll_fn(|cx| {
+            assert!(waiter.poll_next_batch(cx).is_pending());
+            Poll::Ready(())
+        })
+        .await;
+
+        let error = waiter.set_mask(QS_POSTMESSAGE, MWMO_NONE).err().unwrap();
+        assert_eq!(error.code(), E_ILLEGAL_METHOD_CALL);
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(1), LPARAM(0)).unwrap();
+        }
+        assert_eq!(waiter.wait().await.unwrap().count(), 1);
+
+        // Between batches, the mask can be changed.
+        waiter.set_mask(QS_POSTMESSAGE, MWMO_NONE).unwrap();
+        poll_fn(|cx| {
+            assert!(waiter.poll_next_batch(cx).is_pending());
+            Poll::Ready(())
+        })
+        .await;
+        assert_eq!(
+            waiter.explain_wait_state().queue_status_flags,
+            QS_POSTMESSAGE
+        );
+
+        unsafe {
+            PostThreadMessageW(GetCurrentThreadId(), WM_USER, WPARAM(2), LPARAM(0)).unwrap();
+        }
+        assert_eq!(waiter.wait().await.unwrap().count(), 1);
+    });
+}